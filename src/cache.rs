@@ -1,25 +1,84 @@
+mod chunked;
+mod command;
 mod filesystem;
 mod google_cloud_storage;
 mod http;
+mod mirrors;
+mod oci;
+mod redis;
+mod remote_execution;
+mod replicated;
+mod routed;
+mod sharded;
+mod webdav;
+mod zstd;
 
-use crate::channel;
+use crate::{channel, git_lfs};
+use ::http::StatusCode;
 use futures::TryFutureExt;
 use serde::{Deserialize, Serialize};
+use std::io;
 use std::str::FromStr;
 
 #[derive(Debug)]
 pub enum Cache {
+    Chunked(chunked::Cache),
+    Command(command::Cache),
     Filesystem(filesystem::Cache),
     GoogleCloudStorage(google_cloud_storage::Cache),
     Http(http::Cache),
+    Mirrors(mirrors::Cache),
+    Oci(oci::Cache),
+    Redis(redis::Cache),
+    RemoteExecution(remote_execution::Cache),
+    Replicated(replicated::Cache),
+    Routed(routed::Cache),
+    Sharded(sharded::Cache),
+    Webdav(webdav::Cache),
+    Zstd(zstd::Cache),
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Args {
+    Chunked(chunked::Args),
+    Command(command::Args),
     Filesystem(filesystem::Args),
     GoogleCloudStorage(google_cloud_storage::Args),
     Http(http::Args),
+    Mirrors(mirrors::Args),
+    Oci(oci::Args),
+    Redis(redis::Args),
+    RemoteExecution(remote_execution::Args),
+    Replicated(replicated::Args),
+    Routed(routed::Args),
+    Sharded(sharded::Args),
+    Webdav(webdav::Args),
+    Zstd(zstd::Args),
+}
+
+// for a get whose stored length differs from the object's, e.g. under the zstd layer
+pub const ANY_SIZE: u64 = u64::MAX;
+
+impl Args {
+    // backends that verify what they store against the oid, so only take the object itself
+    pub fn content_addressed(&self) -> bool {
+        match self {
+            Self::Oci(_) | Self::RemoteExecution(_) => true,
+            Self::Command(_)
+            | Self::Filesystem(_)
+            | Self::GoogleCloudStorage(_)
+            | Self::Http(_)
+            | Self::Redis(_)
+            | Self::Webdav(_) => false,
+            Self::Chunked(args) => args.content_addressed(),
+            Self::Mirrors(args) => args.content_addressed(),
+            Self::Replicated(args) => args.content_addressed(),
+            Self::Routed(args) => args.content_addressed(),
+            Self::Sharded(args) => args.content_addressed(),
+            Self::Zstd(args) => args.content_addressed(),
+        }
+    }
 }
 
 impl FromStr for Args {
@@ -32,14 +91,27 @@ impl FromStr for Args {
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Source {
+    Chunked(chunked::Source),
+    Command(command::Source),
     Filesystem(filesystem::Source),
     GoogleCloudStorage(google_cloud_storage::Source),
     Http(http::Source),
+    Mirrors(mirrors::Source),
+    Oci(oci::Source),
+    Redis(redis::Source),
+    RemoteExecution(remote_execution::Source),
+    Replicated(replicated::Source),
+    Routed(routed::Source),
+    Sharded(sharded::Source),
+    Webdav(webdav::Source),
+    Zstd(zstd::Source),
 }
 
 impl Cache {
     pub async fn new(args: Args) -> anyhow::Result<Self> {
         match args {
+            Args::Chunked(args) => chunked::Cache::new(args).map_ok(Self::Chunked).await,
+            Args::Command(args) => command::Cache::new(args).map_ok(Self::Command).await,
             Args::Filesystem(args) => filesystem::Cache::new(args).map_ok(Self::Filesystem).await,
             Args::GoogleCloudStorage(args) => {
                 google_cloud_storage::Cache::new(args)
@@ -47,6 +119,19 @@ impl Cache {
                     .await
             }
             Args::Http(args) => http::Cache::new(args).map_ok(Self::Http).await,
+            Args::Mirrors(args) => mirrors::Cache::new(args).map_ok(Self::Mirrors).await,
+            Args::Oci(args) => oci::Cache::new(args).map_ok(Self::Oci).await,
+            Args::Redis(args) => redis::Cache::new(args).map_ok(Self::Redis).await,
+            Args::RemoteExecution(args) => {
+                remote_execution::Cache::new(args)
+                    .map_ok(Self::RemoteExecution)
+                    .await
+            }
+            Args::Replicated(args) => replicated::Cache::new(args).map_ok(Self::Replicated).await,
+            Args::Routed(args) => routed::Cache::new(args).map_ok(Self::Routed).await,
+            Args::Sharded(args) => sharded::Cache::new(args).map_ok(Self::Sharded).await,
+            Args::Webdav(args) => webdav::Cache::new(args).map_ok(Self::Webdav).await,
+            Args::Zstd(args) => zstd::Cache::new(args).map_ok(Self::Zstd).await,
         }
     }
 
@@ -57,6 +142,8 @@ impl Cache {
         writer: channel::Writer<'_>,
     ) -> anyhow::Result<Source> {
         match self {
+            Self::Chunked(cache) => cache.get(oid, size, writer).map_ok(Source::Chunked).await,
+            Self::Command(cache) => cache.get(oid, size, writer).map_ok(Source::Command).await,
             Self::Filesystem(cache) => {
                 cache
                     .get(oid, size, writer)
@@ -70,6 +157,25 @@ impl Cache {
                     .await
             }
             Self::Http(cache) => cache.get(oid, size, writer).map_ok(Source::Http).await,
+            Self::Mirrors(cache) => cache.get(oid, size, writer).map_ok(Source::Mirrors).await,
+            Self::Oci(cache) => cache.get(oid, size, writer).map_ok(Source::Oci).await,
+            Self::Redis(cache) => cache.get(oid, size, writer).map_ok(Source::Redis).await,
+            Self::RemoteExecution(cache) => {
+                cache
+                    .get(oid, size, writer)
+                    .map_ok(Source::RemoteExecution)
+                    .await
+            }
+            Self::Replicated(cache) => {
+                cache
+                    .get(oid, size, writer)
+                    .map_ok(Source::Replicated)
+                    .await
+            }
+            Self::Routed(cache) => cache.get(oid, size, writer).map_ok(Source::Routed).await,
+            Self::Sharded(cache) => cache.get(oid, size, writer).map_ok(Source::Sharded).await,
+            Self::Webdav(cache) => cache.get(oid, size, writer).map_ok(Source::Webdav).await,
+            Self::Zstd(cache) => cache.get(oid, size, writer).map_ok(Source::Zstd).await,
         }
     }
 
@@ -80,9 +186,28 @@ impl Cache {
         reader: &channel::Reader<'_>,
     ) -> anyhow::Result<()> {
         match self {
+            Self::Chunked(cache) => cache.put(oid, size, reader).await,
+            Self::Command(cache) => cache.put(oid, size, reader).await,
             Self::Filesystem(cache) => cache.put(oid, size, reader).await,
             Self::GoogleCloudStorage(cache) => cache.put(oid, size, reader).await,
             Self::Http(cache) => cache.put(oid, size, reader).await,
+            Self::Mirrors(cache) => cache.put(oid, size, reader).await,
+            Self::Oci(cache) => cache.put(oid, size, reader).await,
+            Self::Redis(cache) => cache.put(oid, size, reader).await,
+            Self::RemoteExecution(cache) => cache.put(oid, size, reader).await,
+            Self::Replicated(cache) => cache.put(oid, size, reader).await,
+            Self::Routed(cache) => cache.put(oid, size, reader).await,
+            Self::Sharded(cache) => cache.put(oid, size, reader).await,
+            Self::Webdav(cache) => cache.put(oid, size, reader).await,
+            Self::Zstd(cache) => cache.put(oid, size, reader).await,
         }
     }
 }
+
+// a miss, as opposed to a cache that is failing
+pub fn not_found(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<git_lfs::Error>()
+        .is_some_and(|e| e.code == StatusCode::NOT_FOUND)
+        || e.downcast_ref::<io::Error>()
+            .is_some_and(|e| e.kind() == io::ErrorKind::NotFound)
+}
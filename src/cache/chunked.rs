@@ -0,0 +1,213 @@
+use crate::{cache, channel};
+use bytes::Bytes;
+use fastcdc::v2020::AsyncStreamCDC;
+use futures::{StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::pin;
+use tokio::fs;
+use tokio::io::AsyncReadExt;
+use tokio_util::io::StreamReader;
+
+const MIN_SIZE: usize = 1 << 18;
+const AVG_SIZE: usize = 1 << 20;
+const MAX_SIZE: usize = 1 << 22;
+const CONCURRENCY: usize = 8;
+
+#[derive(Debug)]
+pub struct Cache {
+    cache: Box<cache::Cache>,
+    dir: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Args {
+    cache: Box<cache::Args>,
+    // local chunk store, reused across objects
+    dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Source {
+    manifest: Box<cache::Source>,
+    chunks: usize,
+    fetched: usize,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Manifest {
+    chunks: Vec<Chunk>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Chunk {
+    oid: String,
+    size: u64,
+}
+
+impl Args {
+    pub fn content_addressed(&self) -> bool {
+        self.cache.content_addressed()
+    }
+}
+
+impl Cache {
+    pub async fn new(args: Args) -> anyhow::Result<Self> {
+        // the manifest is stored under a key that is not a digest
+        anyhow::ensure!(
+            !args.cache.content_addressed(),
+            "chunked cannot wrap a content-addressed cache",
+        );
+        let dir = if let Some(dir) = args.dir {
+            fs::create_dir_all(&dir).await?;
+            Some(dir.canonicalize()?)
+        } else {
+            None
+        };
+        Ok(Self {
+            cache: Box::new(Box::pin(cache::Cache::new(*args.cache)).await?),
+            dir,
+        })
+    }
+
+    #[tracing::instrument(err, ret)]
+    pub async fn get(
+        &self,
+        oid: &str,
+        size: u64,
+        mut writer: channel::Writer<'_>,
+    ) -> anyhow::Result<Source> {
+        let dir = writer.dir().to_path_buf();
+        let (manifest, data) = self
+            .get_inner(&manifest_key(oid), cache::ANY_SIZE, &dir)
+            .await?;
+        let Manifest { chunks } = serde_json::from_slice(&data)?;
+        anyhow::ensure!(
+            size == cache::ANY_SIZE || chunks.iter().map(|chunk| chunk.size).sum::<u64>() == size
+        );
+
+        let mut fetched = 0;
+        let mut body = pin::pin!(
+            futures::stream::iter(&chunks)
+                .map(|chunk| self.get_chunk(chunk, &dir))
+                .buffered(CONCURRENCY)
+        );
+        while let Some((data, hit)) = body.try_next().await? {
+            writer.write(&data).await?;
+            if !hit {
+                fetched += 1;
+            }
+        }
+        writer.finish().await?;
+
+        Ok(Source {
+            manifest: Box::new(manifest),
+            chunks: chunks.len(),
+            fetched,
+        })
+    }
+
+    #[tracing::instrument(err, ret)]
+    pub async fn put(
+        &self,
+        oid: &str,
+        size: u64,
+        reader: &channel::Reader<'_>,
+    ) -> anyhow::Result<()> {
+        let body = pin::pin!(StreamReader::new(reader.stream()?));
+        let mut chunker = AsyncStreamCDC::new(body, MIN_SIZE, AVG_SIZE, MAX_SIZE);
+        let chunks = chunker
+            .as_stream()
+            .map_err(anyhow::Error::from)
+            .map_ok(|chunk| async move {
+                let data = Bytes::from(chunk.data);
+                let oid = hex::encode(Sha256::digest(&data));
+                // uploaded even when stored locally, since the inner cache may have evicted it
+                self.put_inner(&oid, &data, reader.dir()).await?;
+                self.put_local(&oid, &data).await?;
+                Ok::<_, anyhow::Error>(Chunk {
+                    oid,
+                    size: data.len() as _,
+                })
+            })
+            .try_buffered(CONCURRENCY)
+            .try_collect::<Vec<_>>()
+            .await?;
+        anyhow::ensure!(chunks.iter().map(|chunk| chunk.size).sum::<u64>() == size);
+
+        let manifest = serde_json::to_vec(&Manifest { chunks })?;
+        self.put_inner(&manifest_key(oid), &manifest, reader.dir())
+            .await
+    }
+
+    async fn get_chunk(&self, chunk: &Chunk, dir: &Path) -> anyhow::Result<(Bytes, bool)> {
+        if let Some(path) = self.local_path(&chunk.oid)
+            && let Ok(data) = fs::read(&path).await
+            && data.len() as u64 == chunk.size
+        {
+            Ok((Bytes::from(data), true))
+        } else {
+            let (_, data) = self.get_inner(&chunk.oid, chunk.size, dir).await?;
+            anyhow::ensure!(chunk.oid == hex::encode(Sha256::digest(&data)));
+            self.put_local(&chunk.oid, &data).await?;
+            Ok((data, false))
+        }
+    }
+
+    // staged in dir, the directory of the outer channel
+    async fn get_inner(
+        &self,
+        key: &str,
+        size: u64,
+        dir: &Path,
+    ) -> anyhow::Result<(cache::Source, Bytes)> {
+        let mut channel = channel::new_in(size, dir)?;
+        let source = {
+            let (writer, _) = channel.init()?;
+            Box::pin(self.cache.get(key, size, writer)).await?
+        };
+        let mut data = Vec::new();
+        pin::pin!(StreamReader::new(channel.reader()?.stream()?))
+            .read_to_end(&mut data)
+            .await?;
+        Ok((source, Bytes::from(data)))
+    }
+
+    async fn put_inner(&self, key: &str, data: &[u8], dir: &Path) -> anyhow::Result<()> {
+        let mut channel = channel::new_in(data.len() as _, dir)?;
+        let (mut writer, reader) = channel.init()?;
+        writer.write(data).await?;
+        writer.finish().await?;
+        Box::pin(self.cache.put(key, data.len() as _, &reader)).await
+    }
+
+    async fn put_local(&self, oid: &str, data: &[u8]) -> anyhow::Result<()> {
+        if let Some(path) = self.local_path(oid)
+            && !fs::try_exists(&path).await?
+        {
+            let parent = path
+                .parent()
+                .ok_or_else(|| anyhow::format_err!("missing parent"))?;
+            fs::create_dir_all(&parent).await?;
+            let mut channel = channel::new_in(data.len() as _, parent)?;
+            let (mut writer, _) = channel.init()?;
+            writer.write(data).await?;
+            writer.finish().await?;
+            fs::rename(channel.keep()?, path).await?;
+        }
+        Ok(())
+    }
+
+    fn local_path(&self, oid: &str) -> Option<PathBuf> {
+        let dir = self.dir.as_ref()?;
+        Some(dir.join(&oid[..2]).join(&oid[2..4]).join(oid))
+    }
+}
+
+fn manifest_key(oid: &str) -> String {
+    format!("{oid}.manifest")
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,87 @@
+use crate::{cache, channel};
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use tokio::fs;
+
+async fn put(cache: &cache::Cache, dir: &Path, body: &[u8]) -> anyhow::Result<String> {
+    let oid = hex::encode(Sha256::digest(body));
+    let mut channel = channel::new_in(body.len() as _, dir)?;
+    let (mut writer, reader) = channel.init()?;
+    writer.write(body).await?;
+    writer.finish().await?;
+    cache.put(&oid, body.len() as _, &reader).await?;
+    Ok(oid)
+}
+
+async fn get(
+    cache: &cache::Cache,
+    dir: &Path,
+    oid: &str,
+    size: u64,
+) -> anyhow::Result<(Vec<u8>, cache::Source)> {
+    let mut channel = channel::new_in(size, dir)?;
+    let (writer, _) = channel.init()?;
+    let source = cache.get(oid, size, writer).await?;
+    Ok((fs::read(channel.keep()?).await?, source))
+}
+
+#[tokio::test]
+async fn test() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let cache_dir = temp_dir.path().join("cache");
+    let cache = cache::Cache::new(serde_json::from_value(serde_json::json!({
+        "chunked": {"cache": {"filesystem": {"dir": cache_dir}}},
+    }))?)
+    .await?;
+
+    let mut body = vec![0; 1 << 22];
+    rand::rng().fill(&mut body[..]);
+    let oid = put(&cache, temp_dir.path(), &body).await?;
+    let (data, source) = get(&cache, temp_dir.path(), &oid, body.len() as _).await?;
+    anyhow::ensure!(data == body);
+    let cache::Source::Chunked(source) = source else {
+        anyhow::bail!("unexpected source: {source:?}");
+    };
+    anyhow::ensure!(source.chunks > 1);
+    anyhow::ensure!(source.fetched == source.chunks);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_dedup() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let cache_dir = temp_dir.path().join("cache");
+    let cache = cache::Cache::new(serde_json::from_value(serde_json::json!({
+        "chunked": {
+            "cache": {"filesystem": {"dir": cache_dir}},
+            "dir": temp_dir.path().join("chunks"),
+        },
+    }))?)
+    .await?;
+
+    let mut body = vec![0; 1 << 22];
+    rand::rng().fill(&mut body[..]);
+    let oid = put(&cache, temp_dir.path(), &body).await?;
+    let (data, source) = get(&cache, temp_dir.path(), &oid, body.len() as _).await?;
+    anyhow::ensure!(data == body);
+    let cache::Source::Chunked(source) = source else {
+        anyhow::bail!("unexpected source: {source:?}");
+    };
+    anyhow::ensure!(source.fetched == 0);
+
+    // the inner cache lost every chunk, yet a new version is complete for another client
+    fs::remove_dir_all(&cache_dir).await?;
+    fs::create_dir_all(&cache_dir).await?;
+    body.extend_from_slice(b"hello world\n");
+    let oid = put(&cache, temp_dir.path(), &body).await?;
+    let fresh = cache::Cache::new(serde_json::from_value(serde_json::json!({
+        "chunked": {"cache": {"filesystem": {"dir": cache_dir}}},
+    }))?)
+    .await?;
+    let (data, _) = get(&fresh, temp_dir.path(), &oid, body.len() as _).await?;
+    anyhow::ensure!(data == body);
+
+    Ok(())
+}
@@ -0,0 +1,173 @@
+// each request is a line of JSON on stdin, answered by a line of JSON on stdout
+// -> {"event": "get", "oid": "...", "size": 123, "path": "..."}  (write the object to path)
+// -> {"event": "put", "oid": "...", "size": 123, "path": "..."}  (read the object from path)
+// -> {"event": "exists", "oid": "...", "size": 123}
+// -> {"event": "delete", "oid": "..."}
+// <- {"exists": true} or {"error": {"code": 404, "message": "..."}}
+// a size of 18446744073709551615 means the stored length is unknown, e.g. under the zstd layer
+
+use crate::{channel, git_lfs, jsonl};
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::pin;
+use std::process::Stdio;
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::Mutex;
+
+#[derive(Debug)]
+pub struct Cache {
+    program: PathBuf,
+    #[allow(dead_code)]
+    child: Child,
+    io: Mutex<(jsonl::Writer<ChildStdin>, jsonl::Reader<ChildStdout>)>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Args {
+    program: PathBuf,
+    #[serde(default)]
+    args: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Source {
+    program: PathBuf,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase", tag = "event")]
+enum Request<'a> {
+    Get {
+        oid: &'a str,
+        size: u64,
+        path: &'a Path,
+    },
+    Put {
+        oid: &'a str,
+        size: u64,
+        path: &'a Path,
+    },
+    Exists {
+        oid: &'a str,
+        size: u64,
+    },
+    Delete {
+        oid: &'a str,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+struct Response {
+    #[serde(default)]
+    exists: bool,
+    error: Option<git_lfs::Error>,
+}
+
+impl Cache {
+    pub async fn new(args: Args) -> anyhow::Result<Self> {
+        let mut command = Command::new(&args.program);
+        command
+            .args(&args.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true);
+        tracing::info!(?command);
+        let mut child = command.spawn()?;
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| anyhow::format_err!("missing stdin"))?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| anyhow::format_err!("missing stdout"))?;
+        Ok(Self {
+            program: args.program,
+            child,
+            io: Mutex::new((jsonl::Writer::new(stdin), jsonl::Reader::new(stdout))),
+        })
+    }
+
+    #[tracing::instrument(err, ret)]
+    pub async fn get(
+        &self,
+        oid: &str,
+        size: u64,
+        mut writer: channel::Writer<'_>,
+    ) -> anyhow::Result<Source> {
+        let temp = tempfile::NamedTempFile::new_in(writer.dir())?;
+        self.request(&Request::Get {
+            oid,
+            size,
+            path: temp.path(),
+        })
+        .await?;
+
+        let mut reader = BufReader::new(File::open(temp.path()).await?);
+        loop {
+            let data = reader.fill_buf().await?;
+            if data.is_empty() {
+                break;
+            } else {
+                let len = data.len();
+                writer.write(data).await?;
+                reader.consume(len);
+            }
+        }
+        writer.finish().await?;
+        Ok(Source {
+            program: self.program.clone(),
+        })
+    }
+
+    #[tracing::instrument(err, ret)]
+    pub async fn put(
+        &self,
+        oid: &str,
+        size: u64,
+        reader: &channel::Reader<'_>,
+    ) -> anyhow::Result<()> {
+        let Response { exists, .. } = self.request(&Request::Exists { oid, size }).await?;
+        if !exists {
+            // wait until the whole object is on disk
+            let mut body = pin::pin!(reader.stream()?);
+            while body.try_next().await?.is_some() {}
+            self.request(&Request::Put {
+                oid,
+                size,
+                path: reader.path(),
+            })
+            .await?;
+        }
+        Ok(())
+    }
+
+    // for gc, which has yet to be written
+    #[allow(dead_code)]
+    #[tracing::instrument(err, ret)]
+    pub async fn delete(&self, oid: &str) -> anyhow::Result<()> {
+        self.request(&Request::Delete { oid }).await?;
+        Ok(())
+    }
+
+    async fn request(&self, request: &Request<'_>) -> anyhow::Result<Response> {
+        let mut io = self.io.lock().await;
+        let (stdin, stdout) = &mut *io;
+        stdin.write(request).await?;
+        let response = stdout
+            .read::<Response>()
+            .await?
+            .ok_or_else(|| anyhow::format_err!("unexpected eof"))?;
+        if let Some(e) = response.error {
+            Err(e.into())
+        } else {
+            Ok(response)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,77 @@
+use crate::{cache, channel};
+use sha2::{Digest, Sha256};
+use tokio::fs;
+
+// a plugin keeping objects in a directory, logging the events it sees
+const PLUGIN: &str = r#"
+while read -r line; do
+    event=$(printf '%s' "$line" | jq -r .event)
+    oid=$(printf '%s' "$line" | jq -r .oid)
+    path=$(printf '%s' "$line" | jq -r .path)
+    echo "$event" >> "$1/log"
+    case "$event" in
+        get)
+            if [ -f "$1/$oid" ]; then
+                cp "$1/$oid" "$path" && echo '{}'
+            else
+                echo '{"error": {"code": 404, "message": "missing"}}'
+            fi
+            ;;
+        put) cp "$path" "$1/$oid" && echo '{}' ;;
+        exists) if [ -f "$1/$oid" ]; then echo '{"exists": true}'; else echo '{}'; fi ;;
+        delete) rm -f "$1/$oid" && echo '{}' ;;
+        *) echo '{"error": {"code": 400, "message": "unknown event"}}' ;;
+    esac
+done
+"#;
+
+#[tokio::test]
+async fn test() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let plugin_dir = temp_dir.path().join("plugin");
+    fs::create_dir_all(&plugin_dir).await?;
+    let cache::Cache::Command(cache) =
+        cache::Cache::new(serde_json::from_value(serde_json::json!({
+            "command": {"program": "sh", "args": ["-c", PLUGIN, "sh", plugin_dir]},
+        }))?)
+        .await?
+    else {
+        anyhow::bail!("unexpected cache");
+    };
+
+    let body = b"hello world\n";
+    let oid = hex::encode(Sha256::digest(body));
+    let size = body.len() as u64;
+
+    let mut channel = channel::new_in(size, temp_dir.path())?;
+    let (writer, _) = channel.init()?;
+    let e = cache
+        .get(&oid, size, writer)
+        .await
+        .err()
+        .ok_or_else(|| anyhow::format_err!("unexpected hit"))?;
+    anyhow::ensure!(cache::not_found(&e));
+
+    for _ in 0..2 {
+        let mut channel = channel::new_in(size, temp_dir.path())?;
+        let (mut writer, reader) = channel.init()?;
+        writer.write(body).await?;
+        writer.finish().await?;
+        cache.put(&oid, size, &reader).await?;
+    }
+    anyhow::ensure!(fs::read(plugin_dir.join(&oid)).await? == body);
+
+    let mut channel = channel::new_in(size, temp_dir.path())?;
+    let (writer, _) = channel.init()?;
+    cache.get(&oid, size, writer).await?;
+    anyhow::ensure!(fs::read(channel.keep()?).await? == body);
+
+    cache.delete(&oid).await?;
+    anyhow::ensure!(!fs::try_exists(plugin_dir.join(&oid)).await?);
+
+    // the second put found the object already there
+    let log = fs::read_to_string(plugin_dir.join("log")).await?;
+    anyhow::ensure!(log == "get\nexists\nput\nexists\nget\ndelete\n", "{log}");
+
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use crate::{channel, git_lfs, misc};
+use crate::{channel, git_lfs, misc, timeout};
 use futures::{TryFutureExt, TryStreamExt};
 use headers::ContentLength;
 use http_body::Frame;
@@ -11,12 +11,17 @@ pub struct Cache {
     service: google_cloud_storage::yup_oauth2::Service<misc::Client, misc::Connector>,
     bucket: String,
     prefix: Option<String>,
+    segments: u64,
+    retry: misc::Retry,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Args {
     bucket: String,
     prefix: Option<String>,
+    // concurrent ranges for large objects
+    segments: Option<u64>,
+    timeout: Option<timeout::Args>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -36,13 +41,18 @@ impl fmt::Debug for Cache {
 
 impl Cache {
     pub async fn new(args: Args) -> anyhow::Result<Self> {
-        let service = google_cloud_storage::yup_oauth2::Layer::with_client(misc::client()?)
-            .await?
-            .layer(misc::client()?);
+        let service = google_cloud_storage::yup_oauth2::Layer::with_client(misc::client_with(
+            None,
+            args.timeout.clone(),
+        )?)
+        .await?
+        .layer(misc::client_with(None, args.timeout)?);
         Ok(Self {
             service,
             bucket: args.bucket,
             prefix: args.prefix,
+            segments: args.segments.unwrap_or(misc::SEGMENTS),
+            retry: misc::Retry::config()?,
         })
     }
 
@@ -51,20 +61,32 @@ impl Cache {
         &self,
         oid: &str,
         size: u64,
-        mut writer: channel::Writer<'_>,
+        writer: channel::Writer<'_>,
     ) -> anyhow::Result<Source> {
         let name = self.name(oid);
-        let response = google_cloud_storage::api::xml::get_object::builder(&self.bucket, &name)
-            .send(self.service.clone())
-            .map_err(map_err)
-            .await?;
-        let mut body = response.into_body();
-        while let Some(frame) = body.frame().await.transpose()? {
-            if let Ok(data) = frame.into_data() {
-                writer.write(&data).await?;
-            }
-        }
-        writer.finish().await?;
+        misc::get(
+            |range| {
+                let name = &name;
+                async move {
+                    let mut builder =
+                        google_cloud_storage::api::xml::get_object::builder(&self.bucket, name);
+                    if let Some(range) = range {
+                        builder = builder.typed_header(
+                            headers::Range::bytes(range).map_err(misc::backoff_permanent)?,
+                        );
+                    }
+                    builder
+                        .send(self.service.clone())
+                        .map_err(backoff_map_err)
+                        .await
+                }
+            },
+            size,
+            self.segments,
+            &self.retry,
+            writer,
+        )
+        .await?;
         Ok(Source {
             bucket: self.bucket.clone(),
             name,
@@ -116,3 +138,35 @@ where
         e => e.into(),
     }
 }
+
+fn backoff_map_err<S, B>(e: google_cloud_storage::api::Error<S, B>) -> backoff::Error<anyhow::Error>
+where
+    S: std::error::Error + Send + Sync + 'static,
+    B: std::error::Error + Send + Sync + 'static,
+{
+    match e {
+        google_cloud_storage::api::Error::Status(e) => {
+            let (parts, body) = e.0.into_parts();
+            misc::backoff_error(
+                git_lfs::Error {
+                    code: parts.status,
+                    message: format!("{body:?}"),
+                },
+                &parts.headers,
+            )
+        }
+        // connection and timeout errors are worth retrying, OAuth and configuration errors are not
+        e => {
+            let e = map_err(e);
+            if e.chain().any(|e| {
+                e.is::<hyper_util::client::legacy::Error>()
+                    || e.is::<hyper::Error>()
+                    || e.is::<std::io::Error>()
+            }) {
+                misc::backoff_transient(e)
+            } else {
+                misc::backoff_permanent(e)
+            }
+        }
+    }
+}
@@ -1,26 +1,35 @@
-use crate::{channel, git_lfs, misc};
+use crate::{channel, misc, timeout, tls};
+use bytes::Bytes;
 use futures::{TryFutureExt, TryStreamExt};
 use headers::HeaderMapExt;
-use http::{Request, StatusCode, header};
+use http::{HeaderMap, HeaderValue, Method, Response, header};
 use http_body::Frame;
-use http_body_util::{BodyExt, Empty, StreamBody};
+use http_body_util::{BodyExt, StreamBody};
 use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::path::PathBuf;
 use tokio::fs;
-use tokio::sync::Mutex;
 use url::Url;
 
 pub struct Cache {
     client: misc::Client,
     endpoint: Url,
+    layout: Layout,
     authorization: Option<Authorization>,
+    segments: u64,
+    retry: misc::Retry,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Args {
     endpoint: Url,
+    #[serde(default)]
+    layout: Layout,
     authorization: Option<Authorization>,
+    // concurrent ranges for large objects
+    segments: Option<u64>,
+    tls: Option<tls::Args>,
+    timeout: Option<timeout::Args>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -28,15 +37,33 @@ pub struct Source {
     url: Url,
 }
 
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Layout {
+    // {endpoint}/{oid}
+    #[default]
+    Flat,
+    // {endpoint}/cas/{oid}
+    BazelCas,
+    // {endpoint}/{aa}/{bb}/{oid}
+    Sharded,
+    // e.g. "objects/{aa}/{oid}"
+    Template(String),
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
-enum Authorization {
+pub(super) enum Authorization {
+    Basic {
+        username: String,
+        password_path: PathBuf,
+    },
     Bearer(Bearer),
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
-enum Bearer {
+pub(super) enum Bearer {
     TokenPath(PathBuf),
 }
 
@@ -44,16 +71,27 @@ impl fmt::Debug for Cache {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Cache")
             .field("url", &self.endpoint)
+            .field("layout", &self.layout)
             .finish()
     }
 }
 
 impl Cache {
     pub async fn new(args: Args) -> anyhow::Result<Self> {
+        // otherwise every object would share one url
+        if let Layout::Template(template) = &args.layout {
+            anyhow::ensure!(
+                template.contains("{oid}"),
+                "layout template {template:?} has no {{oid}}",
+            );
+        }
         Ok(Self {
-            client: misc::client()?,
+            client: misc::client_with(args.tls, args.timeout)?,
             endpoint: args.endpoint,
+            layout: args.layout,
             authorization: args.authorization,
+            segments: args.segments.unwrap_or(misc::SEGMENTS),
+            retry: misc::Retry::config()?,
         })
     }
 
@@ -65,58 +103,32 @@ impl Cache {
         writer: channel::Writer<'_>,
     ) -> anyhow::Result<Source> {
         let url = self.url(oid)?;
-        let writer = Mutex::new(writer);
-
-        backoff::future::retry(backoff::ExponentialBackoff::default(), || {
-            let url = &url;
-            let writer = &writer;
-            async move {
-                let builder = Request::get(url.as_ref());
-                let builder = self.authorization(builder).await?;
-                let request = builder
-                    .body(Empty::new().map_err(Box::from).boxed_unsync())
-                    .map_err(misc::backoff_permanent)?;
-                let response = self
-                    .client
-                    .request(request)
-                    .map_err(misc::backoff_transient)
-                    .await?;
-                let (parts, mut body) = response.into_parts();
-                if parts.status.is_success() {
-                    let mut writer = writer.lock().await;
-                    writer.reset().map_err(misc::backoff_permanent).await?;
-                    while let Some(frame) = body
-                        .frame()
-                        .await
-                        .transpose()
-                        .map_err(misc::backoff_transient)?
-                    {
-                        if let Ok(data) = frame.into_data() {
-                            writer.write(&data).map_err(misc::backoff_permanent).await?;
-                        }
-                    }
-                    Ok(())
-                } else {
-                    let body = body
-                        .collect()
-                        .map_err(misc::backoff_transient)
-                        .await?
-                        .to_bytes();
-                    let e = git_lfs::Error {
-                        code: parts.status,
-                        message: format!("{body:?}"),
-                    };
-                    if parts.status == StatusCode::REQUEST_TIMEOUT || parts.status.is_server_error()
-                    {
-                        Err(misc::backoff_transient(e))
-                    } else {
-                        Err(misc::backoff_permanent(e))
+        misc::get(
+            |range| {
+                let url = &url;
+                async move {
+                    let mut headers = HeaderMap::new();
+                    if let Some(range) = range {
+                        headers.typed_insert(
+                            headers::Range::bytes(range).map_err(misc::backoff_permanent)?,
+                        );
                     }
+                    misc::request(
+                        &self.client,
+                        Method::GET,
+                        url.clone(),
+                        self.headers(headers).await?,
+                        Bytes::new(),
+                    )
+                    .await
                 }
-            }
-        })
+            },
+            size,
+            self.segments,
+            &self.retry,
+            writer,
+        )
         .await?;
-        writer.into_inner().finish().await?;
         Ok(Source { url })
     }
 
@@ -129,81 +141,136 @@ impl Cache {
     ) -> anyhow::Result<()> {
         let url = self.url(oid)?;
 
-        backoff::future::retry(backoff::ExponentialBackoff::default(), || async {
-            let builder = Request::put(url.as_ref()).header(header::CONTENT_LENGTH, size);
-            let builder = self.authorization(builder).await?;
-            let request = builder
-                .body(
-                    BodyExt::map_err(
-                        StreamBody::new(
-                            reader
-                                .stream()
-                                .map_err(misc::backoff_permanent)?
-                                .map_ok(Frame::data),
-                        ),
-                        |e| Box::from(anyhow::Error::from(e)),
-                    )
-                    .boxed_unsync(),
+        self.retry
+            .run(|| async {
+                let mut headers = HeaderMap::new();
+                headers.insert(header::CONTENT_LENGTH, HeaderValue::from(size));
+                let response = misc::request_with(
+                    &self.client,
+                    Method::PUT,
+                    url.clone(),
+                    self.headers(headers).await?,
+                    || {
+                        Ok(BodyExt::map_err(
+                            StreamBody::new(reader.stream()?.map_ok(Frame::data)),
+                            |e| Box::from(anyhow::Error::from(e)),
+                        )
+                        .boxed_unsync())
+                    },
+                )
+                .await?;
+                let (parts, body) = response.into_parts();
+                if parts.status.is_success() {
+                    Ok(())
+                } else {
+                    let body = body
+                        .collect()
+                        .map_err(misc::backoff_transient)
+                        .await?
+                        .to_bytes();
+                    Err(misc::backoff_status(parts.status, &parts.headers, body))
+                }
+            })
+            .await
+    }
+
+    // any other method, retried like get and put; the caller interprets the status
+    pub(super) async fn request(
+        &self,
+        method: Method,
+        url: &Url,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> anyhow::Result<Response<Bytes>> {
+        self.retry
+            .run(|| async {
+                let response = misc::request(
+                    &self.client,
+                    method.clone(),
+                    url.clone(),
+                    self.headers(headers.clone()).await?,
+                    body.clone(),
                 )
-                .map_err(misc::backoff_permanent)?;
-            let response = self
-                .client
-                .request(request)
-                .map_err(misc::backoff_transient)
                 .await?;
-            let (parts, body) = response.into_parts();
-            if parts.status.is_success() {
-                Ok(())
-            } else {
+                let (parts, body) = response.into_parts();
                 let body = body
                     .collect()
                     .map_err(misc::backoff_transient)
                     .await?
                     .to_bytes();
-                let e = git_lfs::Error {
-                    code: parts.status,
-                    message: format!("{body:?}"),
-                };
-                if parts.status == StatusCode::REQUEST_TIMEOUT || parts.status.is_server_error() {
-                    Err(misc::backoff_transient(e))
+                if misc::transient_status(parts.status) {
+                    Err(misc::backoff_status(parts.status, &parts.headers, body))
                 } else {
-                    Err(misc::backoff_permanent(e))
+                    Ok(Response::from_parts(parts, body))
                 }
-            }
-        })
-        .await
+            })
+            .await
+    }
+
+    pub(super) fn endpoint(&self) -> &Url {
+        &self.endpoint
     }
 
-    fn url(&self, oid: &str) -> anyhow::Result<Url> {
+    pub(super) fn url(&self, oid: &str) -> anyhow::Result<Url> {
         let mut url = self.endpoint.clone();
-        misc::path_segments_mut(&mut url)?.push(oid);
+        {
+            let mut path_segments = misc::path_segments_mut(&mut url)?;
+            match &self.layout {
+                Layout::Flat => {
+                    path_segments.push(oid);
+                }
+                Layout::BazelCas => {
+                    path_segments.push("cas").push(oid);
+                }
+                Layout::Sharded => {
+                    path_segments.push(&oid[..2]).push(&oid[2..4]).push(oid);
+                }
+                Layout::Template(template) => {
+                    let path = template
+                        .replace("{aa}", &oid[..2])
+                        .replace("{bb}", &oid[2..4])
+                        .replace("{oid}", oid);
+                    path_segments.extend(path.split('/').filter(|segment| !segment.is_empty()));
+                }
+            }
+        }
         Ok(url)
     }
 
-    async fn authorization(
+    async fn headers(
         &self,
-        mut builder: http::request::Builder,
-    ) -> Result<http::request::Builder, backoff::Error<anyhow::Error>> {
-        if let Some(headers) = builder.headers_mut() {
-            match &self.authorization {
-                Some(Authorization::Bearer(bearer)) => {
-                    let token = match bearer {
-                        Bearer::TokenPath(path) => {
-                            fs::read_to_string(path)
-                                .map_err(anyhow::Error::from)
-                                .map_err(backoff::Error::permanent)
-                                .await?
-                        }
-                    };
-                    headers.typed_insert(
-                        headers::Authorization::bearer(token.trim())
-                            .map_err(anyhow::Error::from)
-                            .map_err(backoff::Error::permanent)?,
-                    );
-                }
-                None => (),
+        mut headers: HeaderMap,
+    ) -> Result<HeaderMap, backoff::Error<anyhow::Error>> {
+        if let Some(authorization) = &self.authorization {
+            authorization
+                .insert(&mut headers)
+                .map_err(misc::backoff_permanent)
+                .await?;
+        }
+        Ok(headers)
+    }
+}
+
+impl Authorization {
+    pub(super) async fn insert(&self, headers: &mut HeaderMap) -> anyhow::Result<()> {
+        match self {
+            Self::Basic {
+                username,
+                password_path,
+            } => {
+                let password = fs::read_to_string(password_path).await?;
+                headers.typed_insert(headers::Authorization::basic(username, password.trim()));
+            }
+            Self::Bearer(bearer) => {
+                let token = match bearer {
+                    Bearer::TokenPath(path) => fs::read_to_string(path).await?,
+                };
+                headers.typed_insert(headers::Authorization::bearer(token.trim())?);
             }
         }
-        Ok(builder)
+        Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,50 @@
+use crate::cache;
+
+const OID: &str = "a948904f2f0f479b8f8197694b30184b0d2ed1c1cd2a1ec0fb85d299a192a447";
+
+async fn url(endpoint: &str, layout: serde_json::Value) -> anyhow::Result<String> {
+    let cache = super::Cache::new(serde_json::from_value(serde_json::json!({
+        "endpoint": endpoint,
+        "layout": layout,
+    }))?)
+    .await?;
+    Ok(cache.url(OID)?.to_string())
+}
+
+#[tokio::test]
+async fn test_url() -> anyhow::Result<()> {
+    anyhow::ensure!(
+        url("https://cache.example.com/lfs", "flat".into()).await?
+            == format!("https://cache.example.com/lfs/{OID}")
+    );
+    anyhow::ensure!(
+        url("https://cache.example.com/lfs/", "bazel_cas".into()).await?
+            == format!("https://cache.example.com/lfs/cas/{OID}")
+    );
+    anyhow::ensure!(
+        url("https://cache.example.com/lfs", "sharded".into()).await?
+            == format!("https://cache.example.com/lfs/a9/48/{OID}")
+    );
+    anyhow::ensure!(
+        url(
+            "https://cache.example.com/lfs",
+            serde_json::json!({"template": "/objects//{aa}/{bb}/{oid}.bin"})
+        )
+        .await?
+            == format!("https://cache.example.com/lfs/objects/a9/48/{OID}.bin")
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_template_without_oid() -> anyhow::Result<()> {
+    let result = cache::Cache::new(serde_json::from_value(serde_json::json!({
+        "http": {
+            "endpoint": "https://cache.example.com/lfs",
+            "layout": {"template": "objects/{aa}/{bb}"},
+        },
+    }))?)
+    .await;
+    anyhow::ensure!(result.is_err());
+    Ok(())
+}
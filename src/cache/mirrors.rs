@@ -0,0 +1,197 @@
+use crate::{cache, channel};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+// sha256 of the empty object, which a mirror either has or cheaply reports missing
+const PROBE_OID: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+// objects below this size measure latency rather than throughput
+const SMALL_SIZE: u64 = 1 << 16;
+const ALPHA: f64 = 0.3;
+
+#[derive(Debug)]
+pub struct Cache {
+    mirrors: Vec<(cache::Cache, Mutex<Score>)>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Args {
+    mirrors: Vec<cache::Args>,
+    // milliseconds
+    probe_timeout: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Source {
+    mirror: usize,
+    source: Box<cache::Source>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Score {
+    healthy: bool,
+    // seconds
+    latency: f64,
+    // bytes per second
+    throughput: Option<f64>,
+}
+
+impl Args {
+    pub fn content_addressed(&self) -> bool {
+        self.mirrors.iter().any(cache::Args::content_addressed)
+    }
+}
+
+impl Cache {
+    pub async fn new(args: Args) -> anyhow::Result<Self> {
+        let timeout = args
+            .probe_timeout
+            .map_or(PROBE_TIMEOUT, Duration::from_millis);
+        let mut mirrors = Vec::with_capacity(args.mirrors.len());
+        for args in args.mirrors {
+            mirrors.push(Box::pin(cache::Cache::new(args)).await?);
+        }
+        let scores =
+            futures::future::join_all(mirrors.iter().map(|cache| probe(cache, timeout))).await;
+        for (i, score) in scores.iter().enumerate() {
+            tracing::info!(mirror = i, ?score);
+        }
+        Ok(Self {
+            mirrors: mirrors
+                .into_iter()
+                .zip(scores)
+                .map(|(cache, score)| (cache, Mutex::new(score)))
+                .collect(),
+        })
+    }
+
+    #[tracing::instrument(err, ret)]
+    pub async fn get(
+        &self,
+        oid: &str,
+        size: u64,
+        writer: channel::Writer<'_>,
+    ) -> anyhow::Result<Source> {
+        let mut e = None;
+        for mirror in self.rank(size).await {
+            let (cache, score) = &self.mirrors[mirror];
+            let start = Instant::now();
+            let source = Box::pin(cache.get(oid, size, writer.fork()?)).await;
+            // the bytes written, since size may be cache::ANY_SIZE
+            let written = if source.is_ok() {
+                writer.reader().size()
+            } else {
+                size
+            };
+            score.lock().await.update(written, start.elapsed(), &source);
+            match source {
+                Ok(source) => {
+                    writer.finish().await?;
+                    return Ok(Source {
+                        mirror,
+                        source: Box::new(source),
+                    });
+                }
+                Err(error) => {
+                    tracing::warn!(mirror, ?error);
+                    e = Some(error);
+                }
+            }
+        }
+        Err(e.unwrap_or_else(|| anyhow::format_err!("no mirrors")))
+    }
+
+    #[tracing::instrument(err, ret)]
+    pub async fn put(
+        &self,
+        oid: &str,
+        size: u64,
+        reader: &channel::Reader<'_>,
+    ) -> anyhow::Result<()> {
+        let mut e = None;
+        for mirror in self.rank(size).await {
+            let (cache, score) = &self.mirrors[mirror];
+            let start = Instant::now();
+            let result = Box::pin(cache.put(oid, size, reader)).await;
+            score.lock().await.update(size, start.elapsed(), &result);
+            match result {
+                Ok(()) => return Ok(()),
+                Err(error) => {
+                    tracing::warn!(mirror, ?error);
+                    e = Some(error);
+                }
+            }
+        }
+        Err(e.unwrap_or_else(|| anyhow::format_err!("no mirrors")))
+    }
+
+    // healthy mirrors first, then by expected transfer time
+    async fn rank(&self, size: u64) -> Vec<usize> {
+        let mut estimates = Vec::with_capacity(self.mirrors.len());
+        for (i, (_, score)) in self.mirrors.iter().enumerate() {
+            let score = *score.lock().await;
+            estimates.push((i, !score.healthy, score.estimate(size)));
+        }
+        estimates.sort_by(|a, b| a.1.cmp(&b.1).then(a.2.total_cmp(&b.2)));
+        estimates.into_iter().map(|(i, _, _)| i).collect()
+    }
+}
+
+async fn probe(cache: &cache::Cache, timeout: Duration) -> Score {
+    let mut score = Score {
+        healthy: false,
+        latency: timeout.as_secs_f64(),
+        throughput: None,
+    };
+    // the probe object is empty, nothing is written here
+    let Ok(mut channel) = channel::new_in(0, env::temp_dir()) else {
+        return score;
+    };
+    let Ok((writer, _)) = channel.init() else {
+        return score;
+    };
+    let start = Instant::now();
+    if let Ok(result) = tokio::time::timeout(timeout, cache.get(PROBE_OID, 0, writer)).await
+        && result.as_ref().err().is_none_or(cache::not_found)
+    {
+        score.healthy = true;
+        score.latency = start.elapsed().as_secs_f64();
+    }
+    score
+}
+
+impl Score {
+    fn update<T>(&mut self, size: u64, elapsed: Duration, result: &anyhow::Result<T>) {
+        match result {
+            Ok(_) => {
+                self.healthy = true;
+                if size < SMALL_SIZE {
+                    self.latency += ALPHA * (elapsed.as_secs_f64() - self.latency);
+                } else {
+                    let throughput = size as f64 / (elapsed.as_secs_f64() - self.latency).max(1e-3);
+                    self.throughput = Some(
+                        self.throughput
+                            .map_or(throughput, |t| t + ALPHA * (throughput - t)),
+                    );
+                }
+            }
+            // a miss still tells us the round-trip time
+            Err(e) if cache::not_found(e) => {
+                self.latency += ALPHA * (elapsed.as_secs_f64() - self.latency);
+            }
+            Err(_) => self.healthy = false,
+        }
+    }
+
+    fn estimate(&self, size: u64) -> f64 {
+        self.latency
+            + self
+                .throughput
+                .map_or(0., |throughput| size as f64 / throughput)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,108 @@
+use super::Score;
+use crate::{cache, channel};
+use sha2::{Digest, Sha256};
+use tokio::fs;
+
+#[tokio::test]
+async fn test_rank() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let dirs = [
+        temp_dir.path().join("a"),
+        temp_dir.path().join("b"),
+        temp_dir.path().join("c"),
+    ];
+    let cache::Cache::Mirrors(cache) =
+        cache::Cache::new(serde_json::from_value(serde_json::json!({
+            "mirrors": {"mirrors": [
+                {"filesystem": {"dir": dirs[0]}},
+                {"filesystem": {"dir": dirs[1]}},
+                {"filesystem": {"dir": dirs[2]}},
+            ]},
+        }))?)
+        .await?
+    else {
+        anyhow::bail!("unexpected cache");
+    };
+    let scores = [
+        Score {
+            healthy: false,
+            latency: 0.001,
+            throughput: None,
+        },
+        Score {
+            healthy: true,
+            latency: 0.1,
+            throughput: Some(1e6),
+        },
+        Score {
+            healthy: true,
+            latency: 0.2,
+            throughput: Some(1e8),
+        },
+    ];
+    for ((_, score), value) in cache.mirrors.iter().zip(scores) {
+        *score.lock().await = value;
+    }
+
+    // unhealthy last, then latency for small objects and throughput for large ones
+    anyhow::ensure!(cache.rank(1 << 10).await == [1, 2, 0]);
+    anyhow::ensure!(cache.rank(1 << 30).await == [2, 1, 0]);
+
+    // the best mirror misses, the next one has it
+    let body = b"hello world\n";
+    let oid = hex::encode(Sha256::digest(body));
+    {
+        let mut channel = channel::new_in(body.len() as _, temp_dir.path())?;
+        let (mut writer, reader) = channel.init()?;
+        writer.write(body).await?;
+        writer.finish().await?;
+        cache.mirrors[2]
+            .0
+            .put(&oid, body.len() as _, &reader)
+            .await?;
+    }
+    let mut channel = channel::new_in(body.len() as _, temp_dir.path())?;
+    let (writer, _) = channel.init()?;
+    let source = cache.get(&oid, body.len() as _, writer).await?;
+    anyhow::ensure!(source.mirror == 2);
+    anyhow::ensure!(fs::read(channel.keep()?).await? == body);
+    anyhow::ensure!(cache.mirrors[1].1.lock().await.healthy);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_any_size() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let cache::Cache::Mirrors(cache) =
+        cache::Cache::new(serde_json::from_value(serde_json::json!({
+            "mirrors": {"mirrors": [{"filesystem": {"dir": temp_dir.path().join("a")}}]},
+        }))?)
+        .await?
+    else {
+        anyhow::bail!("unexpected cache");
+    };
+
+    let body = vec![0; 1 << 20];
+    let oid = hex::encode(Sha256::digest(&body));
+    {
+        let mut channel = channel::new_in(body.len() as _, temp_dir.path())?;
+        let (mut writer, reader) = channel.init()?;
+        writer.write(&body).await?;
+        writer.finish().await?;
+        cache.put(&oid, body.len() as _, &reader).await?;
+    }
+    let mut channel = channel::new_in(cache::ANY_SIZE, temp_dir.path())?;
+    let (writer, _) = channel.init()?;
+    cache.get(&oid, cache::ANY_SIZE, writer).await?;
+    anyhow::ensure!(fs::read(channel.keep()?).await? == body);
+
+    // measured from the bytes read, not from the unknown size
+    let score = *cache.mirrors[0].1.lock().await;
+    let throughput = score.throughput.unwrap_or_default();
+    anyhow::ensure!(
+        throughput > 0. && throughput <= body.len() as f64 * 1e3,
+        "{score:?}"
+    );
+    Ok(())
+}
@@ -0,0 +1,431 @@
+// https://github.com/opencontainers/distribution-spec/blob/main/spec.md
+// https://distribution.github.io/distribution/spec/auth/token/
+
+use crate::{channel, git_lfs, misc, timeout};
+use base64::Engine;
+use bytes::Bytes;
+use futures::{TryFutureExt, TryStreamExt};
+use headers::HeaderMapExt;
+use http::{HeaderMap, HeaderValue, Method, Response, StatusCode, header};
+use http_body::Frame;
+use http_body_util::{BodyExt, Empty, Full, StreamBody};
+use hyper::body::Incoming;
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::path::PathBuf;
+use tokio::fs;
+use tokio::process::Command;
+use tokio::sync::Mutex;
+use url::Url;
+
+const EMPTY: &[u8] = b"{}";
+
+pub struct Cache {
+    client: misc::Client,
+    registry: Url,
+    repository: String,
+    credential: Option<Credential>,
+    token: Mutex<Option<SecretString>>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Args {
+    registry: Url,
+    repository: String,
+    authorization: Option<Authorization>,
+    timeout: Option<timeout::Args>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Source {
+    url: Url,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Authorization {
+    DockerConfig { path: Option<PathBuf> },
+    TokenPath(PathBuf),
+}
+
+enum Credential {
+    Basic {
+        username: String,
+        password: SecretString,
+    },
+    Bearer(SecretString),
+}
+
+impl fmt::Debug for Cache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cache")
+            .field("registry", &self.registry)
+            .field("repository", &self.repository)
+            .finish()
+    }
+}
+
+impl Cache {
+    pub async fn new(args: Args) -> anyhow::Result<Self> {
+        let credential = match args.authorization {
+            Some(Authorization::DockerConfig { path }) => {
+                docker_config(path, &args.registry).await?
+            }
+            Some(Authorization::TokenPath(path)) => Some(Credential::Bearer(SecretString::from(
+                fs::read_to_string(path).await?.trim(),
+            ))),
+            None => None,
+        };
+        Ok(Self {
+            client: misc::client_with(None, args.timeout)?,
+            registry: args.registry,
+            repository: args.repository,
+            credential,
+            token: Mutex::new(None),
+        })
+    }
+
+    #[tracing::instrument(err, ret)]
+    pub async fn get(
+        &self,
+        oid: &str,
+        size: u64,
+        mut writer: channel::Writer<'_>,
+    ) -> anyhow::Result<Source> {
+        let url = self.url(&["blobs", &format!("sha256:{oid}")])?;
+        let response = self
+            .request(Method::GET, url.clone(), HeaderMap::new(), || {
+                Ok(Empty::new().map_err(Box::from).boxed_unsync())
+            })
+            .and_then(success)
+            .await?;
+        let mut body = response.into_body();
+        while let Some(frame) = body.frame().await.transpose()? {
+            if let Ok(data) = frame.into_data() {
+                writer.write(&data).await?;
+            }
+        }
+        writer.finish().await?;
+        Ok(Source { url })
+    }
+
+    #[tracing::instrument(err, ret)]
+    pub async fn put(
+        &self,
+        oid: &str,
+        size: u64,
+        reader: &channel::Reader<'_>,
+    ) -> anyhow::Result<()> {
+        if !self.exists(oid).await? {
+            self.upload(oid, size, || {
+                Ok(
+                    BodyExt::map_err(StreamBody::new(reader.stream()?.map_ok(Frame::data)), |e| {
+                        Box::from(anyhow::Error::from(e))
+                    })
+                    .boxed_unsync(),
+                )
+            })
+            .await?;
+        }
+
+        // registries garbage-collect blobs which no manifest refers to
+        let empty = hex::encode(Sha256::digest(EMPTY));
+        if !self.exists(&empty).await? {
+            self.upload(&empty, EMPTY.len() as _, || {
+                Ok(Full::from(EMPTY).map_err(Box::from).boxed_unsync())
+            })
+            .await?;
+        }
+        let manifest = serde_json::to_vec(&serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "config": {
+                "mediaType": "application/vnd.oci.empty.v1+json",
+                "digest": format!("sha256:{empty}"),
+                "size": EMPTY.len(),
+            },
+            "layers": [{
+                "mediaType": "application/octet-stream",
+                "digest": format!("sha256:{oid}"),
+                "size": size,
+            }],
+        }))?;
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/vnd.oci.image.manifest.v1+json"),
+        );
+        self.request(Method::PUT, self.url(&["manifests", oid])?, headers, || {
+            Ok(Full::from(manifest.clone())
+                .map_err(Box::from)
+                .boxed_unsync())
+        })
+        .and_then(success)
+        .await?;
+        Ok(())
+    }
+
+    async fn exists(&self, oid: &str) -> anyhow::Result<bool> {
+        let response = self
+            .request(
+                Method::HEAD,
+                self.url(&["blobs", &format!("sha256:{oid}")])?,
+                HeaderMap::new(),
+                || Ok(Empty::new().map_err(Box::from).boxed_unsync()),
+            )
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            Ok(false)
+        } else {
+            success(response).await?;
+            Ok(true)
+        }
+    }
+
+    async fn upload<F>(&self, oid: &str, size: u64, body: F) -> anyhow::Result<()>
+    where
+        F: Fn() -> anyhow::Result<misc::Body>,
+    {
+        let url = self.url(&["blobs", "uploads", ""])?;
+        let response = self
+            .request(Method::POST, url.clone(), HeaderMap::new(), || {
+                Ok(Empty::new().map_err(Box::from).boxed_unsync())
+            })
+            .and_then(success)
+            .await?;
+        let location = response
+            .headers()
+            .get(header::LOCATION)
+            .ok_or_else(|| anyhow::format_err!("missing location"))?
+            .to_str()?;
+        let mut url = url.join(location)?;
+        url.query_pairs_mut()
+            .append_pair("digest", &format!("sha256:{oid}"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_LENGTH, HeaderValue::from(size));
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/octet-stream"),
+        );
+        self.request(Method::PUT, url, headers, body)
+            .and_then(success)
+            .await?;
+        Ok(())
+    }
+
+    async fn request<F>(
+        &self,
+        method: Method,
+        url: Url,
+        headers: HeaderMap,
+        body: F,
+    ) -> anyhow::Result<Response<Incoming>>
+    where
+        F: Fn() -> anyhow::Result<misc::Body>,
+    {
+        let mut authenticated = false;
+        loop {
+            let mut headers = headers.clone();
+            self.authorize(&mut headers).await?;
+            // redirects, e.g. to a pre-signed URL of object storage, drop the Authorization
+            let response =
+                misc::request_with(&self.client, method.clone(), url.clone(), headers, &body)
+                    .await
+                    .map_err(backoff_into_inner)?;
+            if response.status() == StatusCode::UNAUTHORIZED
+                && !authenticated
+                && let Some(challenge) = response.headers().get(header::WWW_AUTHENTICATE)
+                && self.authenticate(challenge.to_str()?).await?
+            {
+                authenticated = true;
+            } else {
+                return Ok(response);
+            }
+        }
+    }
+
+    async fn authorize(&self, headers: &mut HeaderMap) -> anyhow::Result<()> {
+        if let Some(token) = &*self.token.lock().await {
+            headers.typed_insert(headers::Authorization::bearer(token.expose_secret())?);
+        } else {
+            match &self.credential {
+                Some(Credential::Basic { username, password }) => {
+                    headers.typed_insert(headers::Authorization::basic(
+                        username,
+                        password.expose_secret(),
+                    ));
+                }
+                Some(Credential::Bearer(token)) => {
+                    headers.typed_insert(headers::Authorization::bearer(token.expose_secret())?);
+                }
+                None => (),
+            }
+        }
+        Ok(())
+    }
+
+    // returns whether it is worth retrying
+    async fn authenticate(&self, challenge: &str) -> anyhow::Result<bool> {
+        let Some(params) = challenge.strip_prefix("Bearer ") else {
+            return Ok(false);
+        };
+        let params = challenge_params(params);
+        let mut url: Url = params
+            .get("realm")
+            .ok_or_else(|| anyhow::format_err!("missing realm"))?
+            .parse()?;
+        for key in ["service", "scope"] {
+            if let Some(value) = params.get(key) {
+                url.query_pairs_mut().append_pair(key, value);
+            }
+        }
+
+        let mut headers = HeaderMap::new();
+        if let Some(Credential::Basic { username, password }) = &self.credential {
+            headers.typed_insert(headers::Authorization::basic(
+                username,
+                password.expose_secret(),
+            ));
+        }
+        let response = misc::request(&self.client, Method::GET, url, headers, Bytes::new())
+            .await
+            .map_err(backoff_into_inner)?;
+        let body = success(response).await?.into_body();
+
+        #[derive(Deserialize)]
+        struct B {
+            token: Option<String>,
+            access_token: Option<String>,
+        }
+
+        let body = body.collect().await?.to_bytes();
+        let B {
+            token,
+            access_token,
+        } = serde_json::from_slice(&body)?;
+        let token = token
+            .or(access_token)
+            .ok_or_else(|| anyhow::format_err!("missing token"))?;
+        *self.token.lock().await = Some(SecretString::from(token));
+        Ok(true)
+    }
+
+    fn url(&self, path: &[&str]) -> anyhow::Result<Url> {
+        let mut url = self.registry.clone();
+        misc::path_segments_mut(&mut url)?
+            .push("v2")
+            .extend(self.repository.split('/'))
+            .extend(path);
+        Ok(url)
+    }
+}
+
+// key="value" pairs; quoted values may hold commas, e.g. scope="repository:foo:pull,push"
+fn challenge_params(mut s: &str) -> HashMap<&str, &str> {
+    let mut params = HashMap::new();
+    while let Some((key, rest)) = s.split_once('=') {
+        let (value, rest) = match rest.trim_start().strip_prefix('"') {
+            Some(rest) => rest.split_once('"').unwrap_or((rest, "")),
+            None => rest.split_once(',').unwrap_or((rest, "")),
+        };
+        params.insert(key.trim().trim_start_matches(',').trim(), value.trim());
+        s = rest;
+    }
+    params
+}
+
+fn backoff_into_inner(e: backoff::Error<anyhow::Error>) -> anyhow::Error {
+    match e {
+        backoff::Error::Permanent(e) | backoff::Error::Transient { err: e, .. } => e,
+    }
+}
+
+async fn success(response: Response<Incoming>) -> anyhow::Result<Response<Incoming>> {
+    if response.status().is_success() {
+        Ok(response)
+    } else {
+        let (parts, body) = response.into_parts();
+        let body = body.collect().await?.to_bytes();
+        Err(git_lfs::Error {
+            code: parts.status,
+            message: format!("{body:?}"),
+        }
+        .into())
+    }
+}
+
+// https://docs.docker.com/reference/cli/docker/#configuration-files
+async fn docker_config(
+    path: Option<PathBuf>,
+    registry: &Url,
+) -> anyhow::Result<Option<Credential>> {
+    #[derive(Deserialize)]
+    struct Config {
+        #[serde(default)]
+        auths: HashMap<String, Auth>,
+        #[serde(rename = "credsStore")]
+        creds_store: Option<String>,
+        #[serde(default, rename = "credHelpers")]
+        cred_helpers: HashMap<String, String>,
+    }
+
+    #[derive(Deserialize)]
+    struct Auth {
+        auth: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Helper {
+        username: String,
+        secret: String,
+    }
+
+    let path = if let Some(path) = path {
+        path
+    } else if let Some(dir) = env::var_os("DOCKER_CONFIG") {
+        PathBuf::from(dir).join("config.json")
+    } else {
+        PathBuf::from(env::var_os("HOME").ok_or_else(|| anyhow::format_err!("missing HOME"))?)
+            .join(".docker")
+            .join("config.json")
+    };
+    let config = serde_json::from_slice::<Config>(&fs::read(path).await?)?;
+    let host = registry.authority();
+
+    if let Some(helper) = config
+        .cred_helpers
+        .get(host)
+        .or(config.creds_store.as_ref())
+    {
+        let stdout = misc::spawn(
+            Command::new(format!("docker-credential-{helper}")).arg("get"),
+            Some(host.as_bytes()),
+        )
+        .await?;
+        let Helper { username, secret } = serde_json::from_slice(&stdout)?;
+        Ok(Some(Credential::Basic {
+            username,
+            password: SecretString::from(secret),
+        }))
+    } else if let Some(Auth { auth: Some(auth) }) = config.auths.get(host) {
+        let auth = String::from_utf8(base64::engine::general_purpose::STANDARD.decode(auth)?)?;
+        let (username, password) = auth
+            .split_once(':')
+            .ok_or_else(|| anyhow::format_err!("invalid auth"))?;
+        Ok(Some(Credential::Basic {
+            username: username.to_string(),
+            password: SecretString::from(password),
+        }))
+    } else {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,219 @@
+use crate::{cache, channel};
+use base64::Engine;
+use bytes::Bytes;
+use headers::authorization::{Basic, Bearer};
+use headers::{Authorization, HeaderMapExt};
+use http::{Method, Request, Response, StatusCode, header};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Incoming;
+use hyper_util::rt::TokioIo;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+
+const TOKEN: &str = "t0k3n";
+
+// digests to blobs, and the log of requests
+#[derive(Debug, Default)]
+struct Registry {
+    blobs: HashMap<String, Bytes>,
+    manifests: HashMap<String, Bytes>,
+    log: Vec<String>,
+}
+
+// a stand-in for a registry which only takes bearer tokens, given out for basic credentials
+async fn serve() -> anyhow::Result<(String, Arc<Mutex<Registry>>)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let authority = listener.local_addr()?.to_string();
+    let registry = Arc::new(Mutex::new(Registry::default()));
+    tokio::spawn({
+        let authority = authority.clone();
+        let registry = registry.clone();
+        async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let authority = authority.clone();
+                let registry = registry.clone();
+                tokio::spawn(hyper::server::conn::http1::Builder::new().serve_connection(
+                    TokioIo::new(stream),
+                    hyper::service::service_fn(move |request| {
+                        handle(authority.clone(), registry.clone(), request)
+                    }),
+                ));
+            }
+        }
+    });
+    Ok((authority, registry))
+}
+
+async fn handle(
+    authority: String,
+    registry: Arc<Mutex<Registry>>,
+    request: Request<Incoming>,
+) -> anyhow::Result<Response<Full<Bytes>>> {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let query = request.uri().query().unwrap_or_default().to_string();
+    let headers = request.headers().clone();
+    let body = request.into_body().collect().await?.to_bytes();
+    let mut registry = registry.lock().unwrap();
+    registry.log.push(format!("{method} {path}"));
+
+    if path == "/token" {
+        let basic = headers.typed_get::<Authorization<Basic>>();
+        anyhow::ensure!(query == "service=registry&scope=repository%3Afoo%2Fbar%3Apull%2Cpush");
+        return Ok(
+            if basic
+                .is_some_and(|basic| basic.username() == "user" && basic.password() == "password")
+            {
+                Response::new(Full::from(format!(r#"{{"token": "{TOKEN}"}}"#)))
+            } else {
+                Response::builder()
+                    .status(StatusCode::UNAUTHORIZED)
+                    .body(Full::default())?
+            },
+        );
+    }
+    if headers
+        .typed_get::<Authorization<Bearer>>()
+        .is_none_or(|bearer| bearer.token() != TOKEN)
+    {
+        return Ok(Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .header(
+                header::WWW_AUTHENTICATE,
+                format!(
+                    r#"Bearer realm="http://{authority}/token",service="registry",scope="repository:foo/bar:pull,push""#
+                ),
+            )
+            .body(Full::default())?);
+    }
+
+    let Some(path) = path.strip_prefix("/v2/foo/bar/") else {
+        return Ok(Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Full::default())?);
+    };
+    let blob = path.strip_prefix("blobs/");
+    let response = match (method, blob, path.strip_prefix("manifests/")) {
+        (Method::HEAD | Method::GET, Some(digest), _) => match registry.blobs.get(digest) {
+            Some(data) => Response::new(Full::new(data.clone())),
+            None => Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Full::default())?,
+        },
+        (Method::POST, Some("uploads/"), _) => Response::builder()
+            .status(StatusCode::ACCEPTED)
+            .header(header::LOCATION, "/v2/foo/bar/blobs/uploads/1")
+            .body(Full::default())?,
+        (Method::PUT, Some("uploads/1"), _) => {
+            let digest = query
+                .strip_prefix("digest=sha256%3A")
+                .ok_or_else(|| anyhow::format_err!("missing digest"))?;
+            anyhow::ensure!(digest == hex::encode(Sha256::digest(&body)));
+            registry.blobs.insert(format!("sha256:{digest}"), body);
+            Response::builder()
+                .status(StatusCode::CREATED)
+                .body(Full::default())?
+        }
+        (Method::PUT, _, Some(reference)) => {
+            registry.manifests.insert(reference.to_string(), body);
+            Response::builder()
+                .status(StatusCode::CREATED)
+                .body(Full::default())?
+        }
+        _ => Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .body(Full::default())?,
+    };
+    Ok(response)
+}
+
+#[tokio::test]
+async fn test() -> anyhow::Result<()> {
+    let (authority, registry) = serve().await?;
+    let temp_dir = tempfile::tempdir()?;
+    let config = temp_dir.path().join("config.json");
+    std::fs::write(
+        &config,
+        serde_json::to_vec(&serde_json::json!({
+            "auths": {
+                &authority: {
+                    "auth": base64::engine::general_purpose::STANDARD.encode("user:password"),
+                },
+            },
+        }))?,
+    )?;
+    let cache = cache::Cache::new(serde_json::from_value(serde_json::json!({
+        "oci": {
+            "registry": format!("http://{authority}"),
+            "repository": "foo/bar",
+            "authorization": {"docker_config": {"path": config}},
+        },
+    }))?)
+    .await?;
+
+    let body = b"hello world".repeat(1 << 12);
+    let oid = hex::encode(Sha256::digest(&body));
+    let size = body.len() as u64;
+    let empty = hex::encode(Sha256::digest(b"{}"));
+
+    let mut channel = channel::new_in(size, temp_dir.path())?;
+    let (mut writer, reader) = channel.init()?;
+    writer.write(&body).await?;
+    writer.finish().await?;
+    cache.put(&oid, size, &reader).await?;
+    {
+        let mut registry = registry.lock().unwrap();
+        anyhow::ensure!(
+            registry
+                .blobs
+                .get(&format!("sha256:{oid}"))
+                .map(|data| &data[..])
+                == Some(&body[..])
+        );
+        anyhow::ensure!(registry.blobs.contains_key(&format!("sha256:{empty}")));
+        anyhow::ensure!(registry.manifests.contains_key(&oid));
+        // refused once, then retried with the token
+        let log = std::mem::take(&mut registry.log);
+        anyhow::ensure!(
+            log == [
+                format!("HEAD /v2/foo/bar/blobs/sha256:{oid}"),
+                "GET /token".to_string(),
+                format!("HEAD /v2/foo/bar/blobs/sha256:{oid}"),
+                "POST /v2/foo/bar/blobs/uploads/".to_string(),
+                "PUT /v2/foo/bar/blobs/uploads/1".to_string(),
+                format!("HEAD /v2/foo/bar/blobs/sha256:{empty}"),
+                "POST /v2/foo/bar/blobs/uploads/".to_string(),
+                "PUT /v2/foo/bar/blobs/uploads/1".to_string(),
+                format!("PUT /v2/foo/bar/manifests/{oid}"),
+            ],
+            "{log:?}"
+        );
+    }
+
+    // blobs which exist are not uploaded again
+    cache.put(&oid, size, &reader).await?;
+    let log = std::mem::take(&mut registry.lock().unwrap().log);
+    anyhow::ensure!(
+        log == [
+            format!("HEAD /v2/foo/bar/blobs/sha256:{oid}"),
+            format!("HEAD /v2/foo/bar/blobs/sha256:{empty}"),
+            format!("PUT /v2/foo/bar/manifests/{oid}"),
+        ],
+        "{log:?}"
+    );
+
+    let mut channel = channel::new_in(size, temp_dir.path())?;
+    let (writer, reader) = channel.init()?;
+    cache.get(&oid, size, writer).await?;
+    anyhow::ensure!(tokio::fs::read(reader.path()).await? == body);
+
+    let missing = hex::encode(Sha256::digest(b"missing"));
+    let mut channel = channel::new_in(size, temp_dir.path())?;
+    let (writer, _) = channel.init()?;
+    let result = cache.get(&missing, size, writer).await;
+    anyhow::ensure!(result.is_err_and(|e| cache::not_found(&e)));
+
+    Ok(())
+}
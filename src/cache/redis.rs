@@ -0,0 +1,120 @@
+use crate::{cache, channel, git_lfs, timeout};
+use futures::TryStreamExt;
+use http::StatusCode;
+use redis::AsyncCommands;
+use redis::aio::MultiplexedConnection;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::pin;
+
+const MAX_SIZE: u64 = 1 << 20;
+
+pub struct Cache {
+    // requests from concurrent transfers are pipelined on this connection
+    connection: MultiplexedConnection,
+    max_size: u64,
+    ttl: Option<u64>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Args {
+    url: String,
+    max_size: Option<u64>,
+    // seconds, refreshed on every hit
+    ttl: Option<u64>,
+    // only connect and first_byte apply
+    timeout: Option<timeout::Args>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Source {
+    key: String,
+}
+
+impl fmt::Debug for Cache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cache")
+            .field("max_size", &self.max_size)
+            .field("ttl", &self.ttl)
+            .finish()
+    }
+}
+
+impl Cache {
+    pub async fn new(args: Args) -> anyhow::Result<Self> {
+        let client = redis::Client::open(args.url)?;
+        let timeouts = args.timeout.unwrap_or_default().timeouts();
+        let config = redis::AsyncConnectionConfig::new()
+            .set_connection_timeout(timeouts.connect)
+            .set_response_timeout(timeouts.first_byte);
+        Ok(Self {
+            connection: client
+                .get_multiplexed_async_connection_with_config(&config)
+                .await?,
+            max_size: args.max_size.unwrap_or(MAX_SIZE),
+            ttl: args.ttl,
+        })
+    }
+
+    #[tracing::instrument(err, ret)]
+    pub async fn get(
+        &self,
+        oid: &str,
+        size: u64,
+        mut writer: channel::Writer<'_>,
+    ) -> anyhow::Result<Source> {
+        if size != cache::ANY_SIZE && size > self.max_size {
+            // never stored, a miss rather than a failing cache
+            return Err(git_lfs::Error {
+                code: StatusCode::NOT_FOUND,
+                message: format!("{size} bytes exceeds max_size {}", self.max_size),
+            }
+            .into());
+        }
+        let mut connection = self.connection.clone();
+        let data: Option<Vec<u8>> = if let Some(ttl) = self.ttl {
+            connection.get_ex(oid, redis::Expiry::EX(ttl)).await?
+        } else {
+            connection.get(oid).await?
+        };
+        let data = data.ok_or_else(|| git_lfs::Error {
+            code: StatusCode::NOT_FOUND,
+            message: format!("missing key {oid}"),
+        })?;
+        anyhow::ensure!(size == cache::ANY_SIZE || data.len() as u64 == size);
+        writer.write(&data).await?;
+        writer.finish().await?;
+        Ok(Source {
+            key: oid.to_string(),
+        })
+    }
+
+    #[tracing::instrument(err, ret)]
+    pub async fn put(
+        &self,
+        oid: &str,
+        size: u64,
+        reader: &channel::Reader<'_>,
+    ) -> anyhow::Result<()> {
+        // larger objects belong to another backend, e.g. behind routed
+        if size > self.max_size {
+            return Err(git_lfs::Error {
+                code: StatusCode::PAYLOAD_TOO_LARGE,
+                message: format!("{size} bytes exceeds max_size {}", self.max_size),
+            }
+            .into());
+        }
+        let mut data = Vec::with_capacity(size as _);
+        let mut body = pin::pin!(reader.stream()?);
+        while let Some(chunk) = body.try_next().await? {
+            data.extend_from_slice(&chunk);
+        }
+        let mut connection = self.connection.clone();
+        if let Some(ttl) = self.ttl {
+            connection.set_ex::<_, _, ()>(oid, data, ttl).await?;
+        } else {
+            connection.set::<_, _, ()>(oid, data).await?;
+        }
+        Ok(())
+    }
+}
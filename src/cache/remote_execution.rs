@@ -0,0 +1,367 @@
+// https://github.com/bazelbuild/remote-apis/blob/main/build/bazel/remote/execution/v2/remote_execution.proto
+// https://github.com/googleapis/googleapis/blob/master/google/bytestream/bytestream.proto
+
+use super::http::Authorization;
+use crate::{channel, git_lfs, misc, timeout};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures::{FutureExt, StreamExt, TryStreamExt};
+use http::{HeaderMap, Request, StatusCode, header};
+use http_body::Frame;
+use http_body_util::{BodyExt, Full, StreamBody};
+use hyper::body::Incoming;
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use url::Url;
+
+pub struct Cache {
+    client: misc::Client,
+    endpoint: Url,
+    instance_name: Option<String>,
+    authorization: Option<Authorization>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Args {
+    endpoint: Url,
+    instance_name: Option<String>,
+    authorization: Option<Authorization>,
+    timeout: Option<timeout::Args>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Source {
+    endpoint: Url,
+    resource_name: String,
+}
+
+impl fmt::Debug for Cache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cache")
+            .field("endpoint", &self.endpoint)
+            .field("instance_name", &self.instance_name)
+            .finish()
+    }
+}
+
+impl Cache {
+    pub async fn new(args: Args) -> anyhow::Result<Self> {
+        let client =
+            hyper_util::client::legacy::Client::builder(hyper_util::rt::TokioExecutor::new())
+                .http2_only(true)
+                .build(misc::connector(None, args.timeout)?);
+        Ok(Self {
+            client,
+            endpoint: args.endpoint,
+            instance_name: args.instance_name,
+            authorization: args.authorization,
+        })
+    }
+
+    #[tracing::instrument(err, ret)]
+    pub async fn get(
+        &self,
+        oid: &str,
+        size: u64,
+        mut writer: channel::Writer<'_>,
+    ) -> anyhow::Result<Source> {
+        let resource_name = self.resource_name(&format!("blobs/{oid}/{size}"));
+        let request = proto::ReadRequest {
+            resource_name: resource_name.clone(),
+            read_offset: 0,
+            read_limit: 0,
+        };
+        let mut body = self
+            .call(
+                &["google.bytestream.ByteStream", "Read"],
+                Full::new(encode(&request))
+                    .map_err(Box::from)
+                    .boxed_unsync(),
+            )
+            .await?;
+
+        let mut decoder = Decoder::default();
+        let mut trailers = None;
+        while let Some(frame) = body.frame().await.transpose()? {
+            match frame.into_data() {
+                Ok(data) => {
+                    decoder.push(&data);
+                    while let Some(proto::ReadResponse { data }) = decoder.next()? {
+                        writer.write(&data).await?;
+                    }
+                }
+                Err(frame) => trailers = frame.into_trailers().ok(),
+            }
+        }
+        status(&trailers.ok_or_else(|| anyhow::format_err!("missing trailers"))?)?;
+        writer.finish().await?;
+        Ok(Source {
+            endpoint: self.endpoint.clone(),
+            resource_name,
+        })
+    }
+
+    #[tracing::instrument(err, ret)]
+    pub async fn put(
+        &self,
+        oid: &str,
+        size: u64,
+        reader: &channel::Reader<'_>,
+    ) -> anyhow::Result<()> {
+        let digest = proto::Digest {
+            hash: oid.to_string(),
+            size_bytes: size as _,
+        };
+        let request = proto::FindMissingBlobsRequest {
+            instance_name: self.instance_name.clone().unwrap_or_default(),
+            blob_digests: vec![digest],
+            digest_function: proto::DIGEST_FUNCTION_SHA256,
+        };
+        let response: proto::FindMissingBlobsResponse = self
+            .unary(
+                &[
+                    "build.bazel.remote.execution.v2.ContentAddressableStorage",
+                    "FindMissingBlobs",
+                ],
+                Full::new(encode(&request))
+                    .map_err(Box::from)
+                    .boxed_unsync(),
+            )
+            .await?;
+        if response.missing_blob_digests.is_empty() {
+            return Ok(());
+        }
+
+        let resource_name = self.resource_name(&format!(
+            "uploads/{}/blobs/{oid}/{size}",
+            uuid::Uuid::new_v4(),
+        ));
+        let mut write_offset = 0;
+        let body = reader
+            .stream()?
+            .map_ok({
+                let resource_name = resource_name.clone();
+                move |data| {
+                    let request = proto::WriteRequest {
+                        resource_name: resource_name.clone(),
+                        write_offset,
+                        finish_write: false,
+                        data,
+                    };
+                    write_offset += request.data.len() as i64;
+                    encode(&request)
+                }
+            })
+            .chain(
+                async move {
+                    Ok(encode(&proto::WriteRequest {
+                        resource_name,
+                        write_offset: size as _,
+                        finish_write: true,
+                        data: Bytes::new(),
+                    }))
+                }
+                .into_stream(),
+            );
+        let body = BodyExt::map_err(StreamBody::new(body.map_ok(Frame::data)), |e| {
+            Box::from(anyhow::Error::from(e))
+        })
+        .boxed_unsync();
+        let proto::WriteResponse { committed_size } = self
+            .unary(&["google.bytestream.ByteStream", "Write"], body)
+            .await?;
+        anyhow::ensure!(committed_size == size as i64);
+        Ok(())
+    }
+
+    fn resource_name(&self, name: &str) -> String {
+        if let Some(instance_name) = &self.instance_name {
+            format!("{instance_name}/{name}")
+        } else {
+            name.to_string()
+        }
+    }
+
+    async fn unary<M>(&self, path: &[&str], body: misc::Body) -> anyhow::Result<M>
+    where
+        M: Message + Default,
+    {
+        let body = self.call(path, body).await?.collect().await?;
+        status(
+            body.trailers()
+                .ok_or_else(|| anyhow::format_err!("missing trailers"))?,
+        )?;
+        let mut decoder = Decoder::default();
+        decoder.push(&body.to_bytes());
+        decoder
+            .next()?
+            .ok_or_else(|| anyhow::format_err!("missing response"))
+    }
+
+    async fn call(&self, path: &[&str], body: misc::Body) -> anyhow::Result<Incoming> {
+        let mut url = self.endpoint.clone();
+        misc::path_segments_mut(&mut url)?.extend(path);
+        let mut builder = Request::post(url.as_ref())
+            .header(header::CONTENT_TYPE, "application/grpc")
+            .header(header::TE, "trailers");
+        if let (Some(headers), Some(authorization)) = (builder.headers_mut(), &self.authorization) {
+            authorization.insert(headers).await?;
+        }
+        let response = self.client.request(builder.body(body)?).await?;
+        let (parts, body) = response.into_parts();
+        if parts.status.is_success() {
+            // trailers-only response
+            if parts.headers.contains_key("grpc-status") {
+                status(&parts.headers)?;
+            }
+            Ok(body)
+        } else {
+            let body = body.collect().await?.to_bytes();
+            Err(git_lfs::Error {
+                code: parts.status,
+                message: format!("{body:?}"),
+            }
+            .into())
+        }
+    }
+}
+
+// https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-HTTP2.md
+fn encode<M>(message: &M) -> Bytes
+where
+    M: Message,
+{
+    let mut buf = BytesMut::with_capacity(5 + message.encoded_len());
+    buf.put_u8(0);
+    buf.put_u32(message.encoded_len() as _);
+    message.encode_raw(&mut buf);
+    buf.freeze()
+}
+
+#[derive(Default)]
+struct Decoder {
+    buf: BytesMut,
+}
+
+impl Decoder {
+    fn push(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    fn next<M>(&mut self) -> anyhow::Result<Option<M>>
+    where
+        M: Message + Default,
+    {
+        if self.buf.len() < 5 {
+            return Ok(None);
+        }
+        anyhow::ensure!(self.buf[0] == 0, "compressed message");
+        let len = u32::from_be_bytes(self.buf[1..5].try_into()?) as usize;
+        if self.buf.len() < 5 + len {
+            Ok(None)
+        } else {
+            self.buf.advance(5);
+            Ok(Some(M::decode(self.buf.split_to(len))?))
+        }
+    }
+}
+
+fn status(headers: &HeaderMap) -> anyhow::Result<()> {
+    let code = headers
+        .get("grpc-status")
+        .ok_or_else(|| anyhow::format_err!("missing grpc-status"))?
+        .to_str()?
+        .parse::<i32>()?;
+    if code == 0 {
+        Ok(())
+    } else {
+        let message = headers
+            .get("grpc-message")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        // https://github.com/grpc/grpc/blob/master/doc/statuscodes.md
+        let status = match code {
+            3 | 9 | 11 => StatusCode::BAD_REQUEST,
+            4 => StatusCode::GATEWAY_TIMEOUT,
+            5 => StatusCode::NOT_FOUND,
+            7 => StatusCode::FORBIDDEN,
+            8 => StatusCode::TOO_MANY_REQUESTS,
+            12 => StatusCode::NOT_IMPLEMENTED,
+            14 => StatusCode::SERVICE_UNAVAILABLE,
+            16 => StatusCode::UNAUTHORIZED,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Err(git_lfs::Error {
+            code: status,
+            message: format!("[grpc-status {code}] {message}"),
+        }
+        .into())
+    }
+}
+
+mod proto {
+    use bytes::Bytes;
+
+    pub const DIGEST_FUNCTION_SHA256: i32 = 1;
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Digest {
+        #[prost(string, tag = "1")]
+        pub hash: String,
+        #[prost(int64, tag = "2")]
+        pub size_bytes: i64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct FindMissingBlobsRequest {
+        #[prost(string, tag = "1")]
+        pub instance_name: String,
+        #[prost(message, repeated, tag = "2")]
+        pub blob_digests: Vec<Digest>,
+        #[prost(int32, tag = "3")]
+        pub digest_function: i32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct FindMissingBlobsResponse {
+        #[prost(message, repeated, tag = "2")]
+        pub missing_blob_digests: Vec<Digest>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ReadRequest {
+        #[prost(string, tag = "1")]
+        pub resource_name: String,
+        #[prost(int64, tag = "2")]
+        pub read_offset: i64,
+        #[prost(int64, tag = "3")]
+        pub read_limit: i64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ReadResponse {
+        #[prost(bytes = "bytes", tag = "10")]
+        pub data: Bytes,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct WriteRequest {
+        #[prost(string, tag = "1")]
+        pub resource_name: String,
+        #[prost(int64, tag = "2")]
+        pub write_offset: i64,
+        #[prost(bool, tag = "3")]
+        pub finish_write: bool,
+        #[prost(bytes = "bytes", tag = "10")]
+        pub data: Bytes,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct WriteResponse {
+        #[prost(int64, tag = "1")]
+        pub committed_size: i64,
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,159 @@
+use super::{Decoder, encode, proto};
+use crate::{cache, channel};
+use bytes::Bytes;
+use http::{HeaderMap, HeaderValue, Request, Response, header};
+use http_body::Frame;
+use http_body_util::{BodyExt, StreamBody};
+use hyper::body::Incoming;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use tokio::fs;
+use tokio::net::TcpListener;
+
+type Store = Arc<Mutex<HashMap<String, Bytes>>>;
+type Body = StreamBody<futures::stream::Iter<std::vec::IntoIter<Result<Frame<Bytes>, Infallible>>>>;
+
+// a stand-in for a CAS server, keeping blobs in memory
+async fn serve() -> anyhow::Result<(String, Store)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let endpoint = format!("http://{}/", listener.local_addr()?);
+    let store = Store::default();
+    tokio::spawn({
+        let store = store.clone();
+        async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let store = store.clone();
+                tokio::spawn(
+                    hyper::server::conn::http2::Builder::new(TokioExecutor::new())
+                        .serve_connection(
+                            TokioIo::new(stream),
+                            hyper::service::service_fn(move |request| {
+                                handle(store.clone(), request)
+                            }),
+                        ),
+                );
+            }
+        }
+    });
+    Ok((endpoint, store))
+}
+
+async fn handle(store: Store, request: Request<Incoming>) -> anyhow::Result<Response<Body>> {
+    let path = request.uri().path().to_string();
+    let mut decoder = Decoder::default();
+    decoder.push(&request.into_body().collect().await?.to_bytes());
+
+    let (messages, code) = match path.as_str() {
+        "/build.bazel.remote.execution.v2.ContentAddressableStorage/FindMissingBlobs" => {
+            let request = decoder
+                .next::<proto::FindMissingBlobsRequest>()?
+                .ok_or_else(|| anyhow::format_err!("missing request"))?;
+            let store = store.lock().unwrap();
+            let missing_blob_digests = request
+                .blob_digests
+                .into_iter()
+                .filter(|digest| !store.contains_key(&digest.hash))
+                .collect();
+            let response = proto::FindMissingBlobsResponse {
+                missing_blob_digests,
+            };
+            (vec![encode(&response)], 0)
+        }
+        "/google.bytestream.ByteStream/Read" => {
+            let request = decoder
+                .next::<proto::ReadRequest>()?
+                .ok_or_else(|| anyhow::format_err!("missing request"))?;
+            let hash = hash(&request.resource_name)?;
+            if let Some(data) = store.lock().unwrap().get(hash) {
+                let messages = data
+                    .chunks(1 << 10)
+                    .map(|chunk| {
+                        encode(&proto::ReadResponse {
+                            data: data.slice_ref(chunk),
+                        })
+                    })
+                    .collect();
+                (messages, 0)
+            } else {
+                (Vec::new(), 5)
+            }
+        }
+        "/google.bytestream.ByteStream/Write" => {
+            let mut resource_name = String::new();
+            let mut data = Vec::new();
+            while let Some(request) = decoder.next::<proto::WriteRequest>()? {
+                anyhow::ensure!(request.write_offset == data.len() as i64);
+                if !request.resource_name.is_empty() {
+                    resource_name = request.resource_name;
+                }
+                data.extend_from_slice(&request.data);
+            }
+            let hash = hash(&resource_name)?;
+            anyhow::ensure!(hash == hex::encode(Sha256::digest(&data)));
+            let response = proto::WriteResponse {
+                committed_size: data.len() as _,
+            };
+            store
+                .lock()
+                .unwrap()
+                .insert(hash.to_string(), Bytes::from(data));
+            (vec![encode(&response)], 0)
+        }
+        _ => (Vec::new(), 12),
+    };
+
+    let mut trailers = HeaderMap::new();
+    trailers.insert("grpc-status", HeaderValue::from(code));
+    let frames = messages
+        .into_iter()
+        .map(Frame::data)
+        .chain([Frame::trailers(trailers)])
+        .map(Ok)
+        .collect::<Vec<_>>();
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, "application/grpc")
+        .body(StreamBody::new(futures::stream::iter(frames)))?)
+}
+
+// .../blobs/{hash}/{size}
+fn hash(resource_name: &str) -> anyhow::Result<&str> {
+    resource_name
+        .rsplit('/')
+        .nth(1)
+        .ok_or_else(|| anyhow::format_err!("invalid resource name"))
+}
+
+#[tokio::test]
+async fn test() -> anyhow::Result<()> {
+    let (endpoint, store) = serve().await?;
+    let cache = cache::Cache::new(serde_json::from_value(serde_json::json!({
+        "remote_execution": {"endpoint": endpoint, "instance_name": "main"},
+    }))?)
+    .await?;
+
+    let temp_dir = tempfile::tempdir()?;
+    let body = b"hello world".repeat(1 << 12);
+    let oid = hex::encode(Sha256::digest(&body));
+    let size = body.len() as u64;
+
+    let mut channel = channel::new_in(size, temp_dir.path())?;
+    let (writer, _) = channel.init()?;
+    anyhow::ensure!(cache.get(&oid, size, writer).await.is_err());
+
+    let mut channel = channel::new_in(size, temp_dir.path())?;
+    let (mut writer, reader) = channel.init()?;
+    writer.write(&body).await?;
+    writer.finish().await?;
+    cache.put(&oid, size, &reader).await?;
+    anyhow::ensure!(store.lock().unwrap().get(&oid).map(|data| &data[..]) == Some(&body[..]));
+
+    let mut channel = channel::new_in(size, temp_dir.path())?;
+    let (writer, _) = channel.init()?;
+    cache.get(&oid, size, writer).await?;
+    anyhow::ensure!(fs::read(channel.keep()?).await? == body);
+
+    Ok(())
+}
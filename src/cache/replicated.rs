@@ -0,0 +1,137 @@
+use crate::{cache, channel};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug)]
+pub struct Cache {
+    replicas: Vec<cache::Cache>,
+    write_quorum: usize,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Args {
+    // reads try replicas in this order
+    replicas: Vec<cache::Args>,
+    // defaults to every replica
+    write_quorum: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Source {
+    replica: usize,
+    repaired: Vec<usize>,
+    source: Box<cache::Source>,
+}
+
+impl Args {
+    pub fn content_addressed(&self) -> bool {
+        self.replicas.iter().any(cache::Args::content_addressed)
+    }
+}
+
+impl Cache {
+    pub async fn new(args: Args) -> anyhow::Result<Self> {
+        let write_quorum = args.write_quorum.unwrap_or(args.replicas.len());
+        anyhow::ensure!(
+            (1..=args.replicas.len()).contains(&write_quorum),
+            "write_quorum must be between 1 and the number of replicas",
+        );
+        let mut replicas = Vec::with_capacity(args.replicas.len());
+        for args in args.replicas {
+            replicas.push(Box::pin(cache::Cache::new(args)).await?);
+        }
+        Ok(Self {
+            replicas,
+            write_quorum,
+        })
+    }
+
+    #[tracing::instrument(err, ret)]
+    pub async fn get(
+        &self,
+        oid: &str,
+        size: u64,
+        writer: channel::Writer<'_>,
+    ) -> anyhow::Result<Source> {
+        let mut e = None;
+        let mut missed = Vec::<usize>::new();
+        for (replica, cache) in self.replicas.iter().enumerate() {
+            match Box::pin(cache.get(oid, size, writer.fork()?)).await {
+                Ok(source) => {
+                    // the length as stored, since size may be cache::ANY_SIZE
+                    let reader = writer.reader();
+                    writer.finish().await?;
+
+                    // read repair, once the caller has the object, of the replicas which
+                    // missed it; those which failed otherwise are likely still down
+                    let results = futures::future::join_all(
+                        missed
+                            .iter()
+                            .map(|&i| Box::pin(self.replicas[i].put(oid, reader.size(), &reader))),
+                    )
+                    .await;
+                    let mut repaired = Vec::new();
+                    for (i, result) in missed.into_iter().zip(results) {
+                        match result {
+                            Ok(()) => repaired.push(i),
+                            Err(error) => tracing::warn!(replica = i, ?error, "repair failed"),
+                        }
+                    }
+                    return Ok(Source {
+                        replica,
+                        repaired,
+                        source: Box::new(source),
+                    });
+                }
+                Err(error) => {
+                    tracing::warn!(replica, ?error);
+                    if cache::not_found(&error) {
+                        missed.push(replica);
+                    }
+                    e = Some(error);
+                }
+            }
+        }
+        Err(e.unwrap_or_else(|| anyhow::format_err!("no replicas")))
+    }
+
+    #[tracing::instrument(err, ret)]
+    pub async fn put(
+        &self,
+        oid: &str,
+        size: u64,
+        reader: &channel::Reader<'_>,
+    ) -> anyhow::Result<()> {
+        let results = futures::future::join_all(
+            self.replicas
+                .iter()
+                .map(|cache| Box::pin(cache.put(oid, size, reader))),
+        )
+        .await;
+        let mut written = 0;
+        let mut e = None;
+        for (replica, result) in results.into_iter().enumerate() {
+            match result {
+                Ok(()) => written += 1,
+                Err(error) => {
+                    // left for read repair
+                    tracing::warn!(replica, ?error);
+                    e = Some(error);
+                }
+            }
+        }
+        if written >= self.write_quorum {
+            Ok(())
+        } else {
+            Err(e
+                .unwrap_or_else(|| anyhow::format_err!("no replicas"))
+                .context(format!(
+                    "{written} of {} replicas written, quorum is {}",
+                    self.replicas.len(),
+                    self.write_quorum,
+                )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
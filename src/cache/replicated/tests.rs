@@ -0,0 +1,94 @@
+use crate::{cache, channel};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+fn path(dir: &Path, oid: &str) -> PathBuf {
+    dir.join(&oid[..2]).join(&oid[2..4]).join(oid)
+}
+
+#[tokio::test]
+async fn test_read_repair() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let dirs = [temp_dir.path().join("a"), temp_dir.path().join("b")];
+    let cache = cache::Cache::new(serde_json::from_value(serde_json::json!({
+        "replicated": {"replicas": [
+            {"filesystem": {"dir": dirs[0]}},
+            {"filesystem": {"dir": dirs[1]}},
+        ]},
+    }))?)
+    .await?;
+
+    let body = b"hello world\n";
+    let oid = hex::encode(Sha256::digest(body));
+    {
+        let mut channel = channel::new_in(body.len() as _, temp_dir.path())?;
+        let (mut writer, reader) = channel.init()?;
+        writer.write(body).await?;
+        writer.finish().await?;
+        cache.put(&oid, body.len() as _, &reader).await?;
+    }
+    fs::remove_file(path(&dirs[0], &oid)).await?;
+
+    let mut channel = channel::new_in(body.len() as _, temp_dir.path())?;
+    let (writer, _) = channel.init()?;
+    let source = cache.get(&oid, body.len() as _, writer).await?;
+    anyhow::ensure!(fs::read(channel.keep()?).await? == body);
+    let cache::Source::Replicated(source) = source else {
+        anyhow::bail!("unexpected source: {source:?}");
+    };
+    anyhow::ensure!(source.replica == 1);
+    anyhow::ensure!(source.repaired == [0]);
+    anyhow::ensure!(fs::read(path(&dirs[0], &oid)).await? == body);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_read_repair_misses_only() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let dirs = [
+        temp_dir.path().join("a"),
+        temp_dir.path().join("b"),
+        temp_dir.path().join("c"),
+    ];
+    let cache = cache::Cache::new(serde_json::from_value(serde_json::json!({
+        "zstd": {"cache": {"replicated": {
+            "replicas": [
+                {"filesystem": {"dir": dirs[0]}},
+                {"filesystem": {"dir": dirs[1]}},
+                {"filesystem": {"dir": dirs[2]}},
+            ],
+            "write_quorum": 1,
+        }}},
+    }))?)
+    .await?;
+
+    let body = b"hello world\n".repeat(1 << 12);
+    let oid = hex::encode(Sha256::digest(&body));
+    // b fails rather than misses
+    fs::create_dir_all(path(&dirs[1], &oid)).await?;
+    {
+        let mut channel = channel::new_in(body.len() as _, temp_dir.path())?;
+        let (mut writer, reader) = channel.init()?;
+        writer.write(&body).await?;
+        writer.finish().await?;
+        cache.put(&oid, body.len() as _, &reader).await?;
+    }
+    fs::remove_file(path(&dirs[0], &oid)).await?;
+
+    let mut channel = channel::new_in(body.len() as _, temp_dir.path())?;
+    let (writer, _) = channel.init()?;
+    let source = cache.get(&oid, body.len() as _, writer).await?;
+    anyhow::ensure!(fs::read(channel.keep()?).await? == body);
+    let source = serde_json::to_value(&source)?;
+    let source = &source["zstd"]["source"]["replicated"];
+    anyhow::ensure!(source["replica"] == 2, "{source}");
+    anyhow::ensure!(source["repaired"] == serde_json::json!([0]), "{source}");
+    // repaired with the stored, compressed entry
+    let stored = fs::read(path(&dirs[2], &oid)).await?;
+    anyhow::ensure!(stored.len() < body.len());
+    anyhow::ensure!(fs::read(path(&dirs[0], &oid)).await? == stored);
+
+    Ok(())
+}
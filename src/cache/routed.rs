@@ -0,0 +1,133 @@
+use crate::{cache, channel, git_lfs};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug)]
+pub struct Cache {
+    routes: Vec<(Rule, cache::Cache)>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Args {
+    // the first matching route wins
+    routes: Vec<Route>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Source {
+    route: usize,
+    source: Box<cache::Source>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct Route {
+    #[serde(flatten)]
+    rule: Rule,
+    cache: cache::Args,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct Rule {
+    // exclusive
+    max_size: Option<u64>,
+    oid_prefix: Option<String>,
+}
+
+impl Args {
+    pub fn content_addressed(&self) -> bool {
+        self.routes
+            .iter()
+            .any(|route| route.cache.content_addressed())
+    }
+}
+
+impl Cache {
+    pub async fn new(args: Args) -> anyhow::Result<Self> {
+        let mut routes = Vec::with_capacity(args.routes.len());
+        for Route { rule, cache } in args.routes {
+            routes.push((rule, Box::pin(cache::Cache::new(cache)).await?));
+        }
+        Ok(Self { routes })
+    }
+
+    #[tracing::instrument(err, ret)]
+    pub async fn get(
+        &self,
+        oid: &str,
+        size: u64,
+        writer: channel::Writer<'_>,
+    ) -> anyhow::Result<Source> {
+        if size != cache::ANY_SIZE {
+            let (route, cache) = self.route(oid, size).ok_or_else(|| git_lfs::Error {
+                code: StatusCode::NOT_FOUND,
+                message: format!("no route for {oid} ({size} bytes)"),
+            })?;
+            let source = Box::pin(cache.get(oid, size, writer)).await?;
+            return Ok(Source {
+                route,
+                source: Box::new(source),
+            });
+        }
+
+        // the stored length is unknown, so any route with a matching prefix may have it
+        for (route, (rule, cache)) in self.routes.iter().enumerate() {
+            if !rule.matches_oid(oid) {
+                continue;
+            }
+            match Box::pin(cache.get(oid, size, writer.fork()?)).await {
+                Ok(source) => {
+                    writer.finish().await?;
+                    return Ok(Source {
+                        route,
+                        source: Box::new(source),
+                    });
+                }
+                Err(error) if cache::not_found(&error) => (),
+                Err(error) => return Err(error),
+            }
+        }
+        Err(git_lfs::Error {
+            code: StatusCode::NOT_FOUND,
+            message: format!("no route has {oid}"),
+        }
+        .into())
+    }
+
+    #[tracing::instrument(err, ret)]
+    pub async fn put(
+        &self,
+        oid: &str,
+        size: u64,
+        reader: &channel::Reader<'_>,
+    ) -> anyhow::Result<()> {
+        // objects no route covers are simply not cached
+        let Some((_, cache)) = self.route(oid, size) else {
+            tracing::info!("no route");
+            return Ok(());
+        };
+        Box::pin(cache.put(oid, size, reader)).await
+    }
+
+    fn route(&self, oid: &str, size: u64) -> Option<(usize, &cache::Cache)> {
+        self.routes
+            .iter()
+            .enumerate()
+            .find(|(_, (rule, _))| rule.matches(oid, size))
+            .map(|(i, (_, cache))| (i, cache))
+    }
+}
+
+impl Rule {
+    fn matches(&self, oid: &str, size: u64) -> bool {
+        self.max_size.is_none_or(|max_size| size < max_size) && self.matches_oid(oid)
+    }
+
+    fn matches_oid(&self, oid: &str) -> bool {
+        self.oid_prefix
+            .as_ref()
+            .is_none_or(|oid_prefix| oid.starts_with(oid_prefix))
+    }
+}
+
+#[cfg(test)]
+mod tests;
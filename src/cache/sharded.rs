@@ -0,0 +1,104 @@
+use crate::{cache, channel};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+#[derive(Debug)]
+pub struct Cache {
+    nodes: Vec<(String, cache::Cache)>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Args {
+    // keyed by a stable name, so that adding a node only moves the oids it wins
+    nodes: BTreeMap<String, cache::Args>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Source {
+    node: String,
+    source: Box<cache::Source>,
+}
+
+impl Args {
+    pub fn content_addressed(&self) -> bool {
+        self.nodes.values().any(cache::Args::content_addressed)
+    }
+}
+
+impl Cache {
+    pub async fn new(args: Args) -> anyhow::Result<Self> {
+        let mut nodes = Vec::with_capacity(args.nodes.len());
+        for (name, args) in args.nodes {
+            nodes.push((name, Box::pin(cache::Cache::new(args)).await?));
+        }
+        Ok(Self { nodes })
+    }
+
+    #[tracing::instrument(err, ret)]
+    pub async fn get(
+        &self,
+        oid: &str,
+        size: u64,
+        writer: channel::Writer<'_>,
+    ) -> anyhow::Result<Source> {
+        let mut e = None;
+        for (name, cache) in self.rank(oid) {
+            match Box::pin(cache.get(oid, size, writer.fork()?)).await {
+                Ok(source) => {
+                    writer.finish().await?;
+                    return Ok(Source {
+                        node: name.clone(),
+                        source: Box::new(source),
+                    });
+                }
+                // the node owning the oid answered, the others would not have it either
+                Err(error) if cache::not_found(&error) => return Err(error),
+                Err(error) => {
+                    tracing::warn!(node = name, ?error);
+                    e = Some(error);
+                }
+            }
+        }
+        Err(e.unwrap_or_else(|| anyhow::format_err!("no nodes")))
+    }
+
+    #[tracing::instrument(err, ret)]
+    pub async fn put(
+        &self,
+        oid: &str,
+        size: u64,
+        reader: &channel::Reader<'_>,
+    ) -> anyhow::Result<()> {
+        let mut e = None;
+        for (name, cache) in self.rank(oid) {
+            match Box::pin(cache.put(oid, size, reader)).await {
+                Ok(()) => return Ok(()),
+                Err(error) => {
+                    tracing::warn!(node = name, ?error);
+                    e = Some(error);
+                }
+            }
+        }
+        Err(e.unwrap_or_else(|| anyhow::format_err!("no nodes")))
+    }
+
+    // https://en.wikipedia.org/wiki/Rendezvous_hashing
+    fn rank(&self, oid: &str) -> Vec<&(String, cache::Cache)> {
+        let mut nodes = self.nodes.iter().collect::<Vec<_>>();
+        nodes.sort_by_cached_key(|(name, _)| std::cmp::Reverse(score(name, oid)));
+        nodes
+    }
+}
+
+fn score(name: &str, oid: &str) -> [u8; 32] {
+    Sha256::new()
+        .chain_update(name)
+        .chain_update(b"\0")
+        .chain_update(oid)
+        .finalize()
+        .into()
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,32 @@
+use super::score;
+use sha2::{Digest, Sha256};
+
+fn winner<'a>(names: &[&'a str], oid: &str) -> anyhow::Result<&'a str> {
+    names
+        .iter()
+        .copied()
+        .max_by_key(|name| score(name, oid))
+        .ok_or_else(|| anyhow::format_err!("no nodes"))
+}
+
+#[test]
+fn test_remap() -> anyhow::Result<()> {
+    let before = ["a", "b", "c", "d"];
+    let after = ["a", "b", "c", "d", "e"];
+    let oids = (0..1000)
+        .map(|i: u32| hex::encode(Sha256::digest(i.to_be_bytes())))
+        .collect::<Vec<_>>();
+
+    let mut moved = 0;
+    for oid in &oids {
+        let (before, after) = (winner(&before, oid)?, winner(&after, oid)?);
+        if before != after {
+            // only to the new node
+            anyhow::ensure!(after == "e");
+            moved += 1;
+        }
+    }
+    // about a fifth
+    anyhow::ensure!((100..300).contains(&moved), "{moved}");
+    Ok(())
+}
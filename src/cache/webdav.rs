@@ -0,0 +1,217 @@
+// http://www.webdav.org/specs/rfc4918.html
+
+use crate::{cache, channel, git_lfs};
+use bytes::Bytes;
+use http::{HeaderMap, HeaderValue, Method, Response, StatusCode, header};
+use quick_xml::events::Event;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use tokio::sync::Mutex;
+use url::Url;
+
+const PROPFIND: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<propfind xmlns="DAV:"><prop><resourcetype/><getcontentlength/></prop></propfind>"#;
+
+#[derive(Debug)]
+pub struct Cache {
+    http: cache::http::Cache,
+    // collections known to exist, so MKCOL is sent once per session
+    collections: Mutex<HashSet<Url>>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Args {
+    #[serde(flatten)]
+    http: cache::http::Args,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Source {
+    #[serde(flatten)]
+    source: cache::http::Source,
+}
+
+#[derive(Debug, Default)]
+struct Entry {
+    href: String,
+    collection: bool,
+    size: u64,
+}
+
+impl Cache {
+    pub async fn new(args: Args) -> anyhow::Result<Self> {
+        Ok(Self {
+            http: cache::http::Cache::new(args.http).await?,
+            collections: Mutex::new(HashSet::new()),
+        })
+    }
+
+    #[tracing::instrument(err, ret)]
+    pub async fn get(
+        &self,
+        oid: &str,
+        size: u64,
+        writer: channel::Writer<'_>,
+    ) -> anyhow::Result<Source> {
+        let source = self.http.get(oid, size, writer).await?;
+        Ok(Source { source })
+    }
+
+    #[tracing::instrument(err, ret)]
+    pub async fn put(
+        &self,
+        oid: &str,
+        size: u64,
+        reader: &channel::Reader<'_>,
+    ) -> anyhow::Result<()> {
+        let url = self.http.url(oid)?;
+        if !self.exists(&url).await? {
+            self.mkcol(&url).await?;
+            self.http.put(oid, size, reader).await?;
+        }
+        Ok(())
+    }
+
+    // every object below the endpoint, for gc and fsck, which have yet to be written
+    #[allow(dead_code)]
+    pub async fn list(&self) -> anyhow::Result<Vec<(String, u64)>> {
+        let mut root = self.http.endpoint().clone();
+        if !root.path().ends_with('/') {
+            root.set_path(&format!("{}/", root.path()));
+        }
+        let mut seen = HashSet::from([root.clone()]);
+        let mut collections = vec![root];
+        let mut objects = Vec::new();
+        while let Some(collection) = collections.pop() {
+            let response = self.propfind(&collection, "1").await?;
+            if response.status() != StatusCode::MULTI_STATUS {
+                return Err(error(response));
+            }
+            for entry in entries(response.body())? {
+                let url = collection.join(&entry.href)?;
+                if entry.collection {
+                    if seen.insert(url.clone()) {
+                        collections.push(url);
+                    }
+                } else if let Some(oid) = url.path_segments().and_then(|mut s| s.next_back())
+                    && oid.len() == 64
+                    && oid.bytes().all(|b| b.is_ascii_hexdigit())
+                {
+                    objects.push((oid.to_string(), entry.size));
+                }
+            }
+        }
+        Ok(objects)
+    }
+
+    async fn exists(&self, url: &Url) -> anyhow::Result<bool> {
+        let response = self.propfind(url, "0").await?;
+        match response.status() {
+            StatusCode::MULTI_STATUS => Ok(true),
+            StatusCode::NOT_FOUND => Ok(false),
+            _ => Err(error(response)),
+        }
+    }
+
+    // parent collections of url below the endpoint, outermost first
+    async fn mkcol(&self, url: &Url) -> anyhow::Result<()> {
+        let skip = self
+            .http
+            .endpoint()
+            .path_segments()
+            .map_or(0, |segments| segments.filter(|s| !s.is_empty()).count());
+        let segments = url
+            .path_segments()
+            .map(|segments| segments.collect::<Vec<_>>())
+            .unwrap_or_default();
+        let mut collection = url.clone();
+        for i in skip..segments.len().saturating_sub(1) {
+            collection.set_path(&format!("/{}/", segments[..=i].join("/")));
+            if self.collections.lock().await.contains(&collection) {
+                continue;
+            }
+            let response = self
+                .http
+                .request(
+                    Method::from_bytes(b"MKCOL")?,
+                    &collection,
+                    HeaderMap::new(),
+                    Bytes::new(),
+                )
+                .await?;
+            // 405 means the collection already exists
+            if response.status().is_success() || response.status() == StatusCode::METHOD_NOT_ALLOWED
+            {
+                self.collections.lock().await.insert(collection.clone());
+            } else {
+                return Err(error(response));
+            }
+        }
+        Ok(())
+    }
+
+    async fn propfind(&self, url: &Url, depth: &'static str) -> anyhow::Result<Response<Bytes>> {
+        let mut headers = HeaderMap::new();
+        headers.insert("depth", HeaderValue::from_static(depth));
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/xml; charset=utf-8"),
+        );
+        self.http
+            .request(
+                Method::from_bytes(b"PROPFIND")?,
+                url,
+                headers,
+                Bytes::from_static(PROPFIND.as_bytes()),
+            )
+            .await
+    }
+}
+
+fn error(response: Response<Bytes>) -> anyhow::Error {
+    git_lfs::Error {
+        code: response.status(),
+        message: format!("{:?}", response.body()),
+    }
+    .into()
+}
+
+fn entries(body: &[u8]) -> anyhow::Result<Vec<Entry>> {
+    let mut reader = quick_xml::Reader::from_reader(body);
+    let mut entries = Vec::new();
+    let mut entry = None;
+    let mut element = Vec::new();
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => match e.local_name().as_ref() {
+                b"response" => entry = Some(Entry::default()),
+                b"collection" => entry.iter_mut().for_each(|entry| entry.collection = true),
+                name => element = name.to_vec(),
+            },
+            Event::Empty(e) if e.local_name().as_ref() == b"collection" => {
+                entry.iter_mut().for_each(|entry| entry.collection = true);
+            }
+            Event::Text(e) => {
+                if let Some(entry) = &mut entry {
+                    let text = e.decode()?;
+                    match &element[..] {
+                        b"href" => entry.href.push_str(text.trim()),
+                        b"getcontentlength" => entry.size = text.trim().parse()?,
+                        _ => (),
+                    }
+                }
+            }
+            Event::End(e) => {
+                if e.local_name().as_ref() == b"response" {
+                    entries.extend(entry.take());
+                }
+                element.clear();
+            }
+            Event::Eof => break Ok(entries),
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,187 @@
+use crate::{cache, channel};
+use bytes::Bytes;
+use http::{Request, Response, StatusCode};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Incoming;
+use hyper_util::rt::TokioIo;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+
+// paths to their contents, None for collections
+type Store = Arc<Mutex<HashMap<String, Option<Bytes>>>>;
+type Log = Arc<Mutex<Vec<String>>>;
+
+// a stand-in for a WebDAV server, which refuses objects whose parent collection is missing
+async fn serve() -> anyhow::Result<(String, Store, Log)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let endpoint = format!("http://{}/dav/", listener.local_addr()?);
+    let store = Store::new(Mutex::new(HashMap::from([("/dav/".to_string(), None)])));
+    let log = Log::default();
+    tokio::spawn({
+        let store = store.clone();
+        let log = log.clone();
+        async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let store = store.clone();
+                let log = log.clone();
+                tokio::spawn(hyper::server::conn::http1::Builder::new().serve_connection(
+                    TokioIo::new(stream),
+                    hyper::service::service_fn(move |request| {
+                        handle(store.clone(), log.clone(), request)
+                    }),
+                ));
+            }
+        }
+    });
+    Ok((endpoint, store, log))
+}
+
+async fn handle(
+    store: Store,
+    log: Log,
+    request: Request<Incoming>,
+) -> anyhow::Result<Response<Full<Bytes>>> {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let depth = request.headers().get("depth").cloned();
+    log.lock().unwrap().push(format!("{method} {path}"));
+    let body = request.into_body().collect().await?.to_bytes();
+
+    let parent = &path[..=path.trim_end_matches('/').rfind('/').unwrap_or_default()];
+    let mut store = store.lock().unwrap();
+    let (status, body) = match method.as_str() {
+        "PROPFIND"
+            if depth.is_some_and(|depth| depth == "1") && store.get(&path) == Some(&None) =>
+        {
+            (StatusCode::MULTI_STATUS, multistatus(&store, &path))
+        }
+        "PROPFIND" if store.contains_key(&path) => (StatusCode::MULTI_STATUS, Bytes::new()),
+        "PROPFIND" => (StatusCode::NOT_FOUND, Bytes::new()),
+        "GET" => match store.get(&path) {
+            Some(Some(data)) => (StatusCode::OK, data.clone()),
+            _ => (StatusCode::NOT_FOUND, Bytes::new()),
+        },
+        "MKCOL" if store.contains_key(&path) => (StatusCode::METHOD_NOT_ALLOWED, Bytes::new()),
+        "MKCOL" | "PUT" if !store.contains_key(parent) => (StatusCode::CONFLICT, Bytes::new()),
+        "MKCOL" => {
+            store.insert(path, None);
+            (StatusCode::CREATED, Bytes::new())
+        }
+        "PUT" => {
+            store.insert(path, Some(body));
+            (StatusCode::CREATED, Bytes::new())
+        }
+        _ => (StatusCode::METHOD_NOT_ALLOWED, Bytes::new()),
+    };
+    Ok(Response::builder().status(status).body(Full::new(body))?)
+}
+
+// the collection and its members, as mod_dav answers with Depth: 1
+fn multistatus(store: &HashMap<String, Option<Bytes>>, collection: &str) -> Bytes {
+    let mut body =
+        String::from(r#"<?xml version="1.0" encoding="utf-8"?><D:multistatus xmlns:D="DAV:">"#);
+    for (path, data) in store {
+        let Some(name) = path.strip_prefix(collection) else {
+            continue;
+        };
+        if !name.is_empty() && name.trim_end_matches('/').contains('/') {
+            continue;
+        }
+        let prop = match data {
+            Some(data) => format!(
+                "<D:resourcetype/><D:getcontentlength>{}</D:getcontentlength>",
+                data.len()
+            ),
+            None => "<D:resourcetype><D:collection/></D:resourcetype>".to_string(),
+        };
+        body.push_str(&format!(
+            "<D:response><D:href>{path}</D:href><D:propstat><D:prop>{prop}</D:prop>\
+             <D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>"
+        ));
+    }
+    body.push_str("</D:multistatus>");
+    Bytes::from(body)
+}
+
+#[tokio::test]
+async fn test() -> anyhow::Result<()> {
+    let (endpoint, store, log) = serve().await?;
+    let cache = cache::Cache::new(serde_json::from_value(serde_json::json!({
+        "webdav": {"endpoint": endpoint, "layout": "sharded"},
+    }))?)
+    .await?;
+
+    let temp_dir = tempfile::tempdir()?;
+    let body = b"hello world".repeat(1 << 12);
+    let oid = hex::encode(Sha256::digest(&body));
+    let size = body.len() as u64;
+    let path = format!("/dav/{}/{}/{oid}", &oid[..2], &oid[2..4]);
+
+    let mut channel = channel::new_in(size, temp_dir.path())?;
+    let (mut writer, reader) = channel.init()?;
+    writer.write(&body).await?;
+    writer.finish().await?;
+    cache.put(&oid, size, &reader).await?;
+    let stored = store.lock().unwrap().get(&path).cloned().flatten();
+    anyhow::ensure!(stored.as_deref() == Some(&body[..]));
+
+    // the object exists, nothing else is sent
+    cache.put(&oid, size, &reader).await?;
+    let log = log.lock().unwrap().clone();
+    anyhow::ensure!(
+        log == [
+            format!("PROPFIND {path}"),
+            format!("MKCOL /dav/{}/", &oid[..2]),
+            format!("MKCOL /dav/{}/{}/", &oid[..2], &oid[2..4]),
+            format!("PUT {path}"),
+            format!("PROPFIND {path}"),
+        ],
+        "{log:?}"
+    );
+
+    let mut channel = channel::new_in(size, temp_dir.path())?;
+    let (writer, reader) = channel.init()?;
+    cache.get(&oid, size, writer).await?;
+    anyhow::ensure!(tokio::fs::read(reader.path()).await? == body);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_list() -> anyhow::Result<()> {
+    let (endpoint, store, _) = serve().await?;
+    let cache::Cache::Webdav(cache) =
+        cache::Cache::new(serde_json::from_value(serde_json::json!({
+            "webdav": {"endpoint": endpoint, "layout": "sharded"},
+        }))?)
+        .await?
+    else {
+        anyhow::bail!("unexpected cache");
+    };
+
+    let temp_dir = tempfile::tempdir()?;
+    let mut expected = Vec::new();
+    for body in [&b"hello world\n"[..], &b"hello webdav\n".repeat(1 << 10)] {
+        let oid = hex::encode(Sha256::digest(body));
+        let size = body.len() as u64;
+        let mut channel = channel::new_in(size, temp_dir.path())?;
+        let (mut writer, reader) = channel.init()?;
+        writer.write(body).await?;
+        writer.finish().await?;
+        cache.put(&oid, size, &reader).await?;
+        expected.push((oid, size));
+    }
+    // anything not named like an object is left out
+    store
+        .lock()
+        .unwrap()
+        .insert("/dav/README".to_string(), Some(Bytes::from("hello")));
+
+    let mut objects = cache.list().await?;
+    objects.sort();
+    expected.sort();
+    anyhow::ensure!(objects == expected, "{objects:?}");
+    Ok(())
+}
@@ -0,0 +1,147 @@
+use crate::{cache, channel};
+use async_compression::Level;
+use async_compression::tokio::bufread::{ZstdDecoder, ZstdEncoder};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use std::pin;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio_util::io::StreamReader;
+
+// entries without this marker were stored uncompressed
+const MAGIC: &[u8] = b"\0git-lfs-cache/zstd\0";
+const SAMPLE_SIZE: u64 = 1 << 17;
+
+#[derive(Debug)]
+pub struct Cache {
+    cache: Box<cache::Cache>,
+    level: Option<i32>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Args {
+    cache: Box<cache::Args>,
+    level: Option<i32>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Source {
+    compressed: bool,
+    source: Box<cache::Source>,
+}
+
+impl Args {
+    pub fn content_addressed(&self) -> bool {
+        self.cache.content_addressed()
+    }
+}
+
+impl Cache {
+    pub async fn new(args: Args) -> anyhow::Result<Self> {
+        // compressed entries are stored under the oid of the uncompressed object
+        anyhow::ensure!(
+            !args.cache.content_addressed(),
+            "zstd cannot wrap a content-addressed cache",
+        );
+        Ok(Self {
+            cache: Box::new(Box::pin(cache::Cache::new(*args.cache)).await?),
+            level: args.level,
+        })
+    }
+
+    #[tracing::instrument(err, ret)]
+    pub async fn get(
+        &self,
+        oid: &str,
+        size: u64,
+        mut writer: channel::Writer<'_>,
+    ) -> anyhow::Result<Source> {
+        let mut channel = channel::new_in(size, writer.dir())?;
+        let source = {
+            let (writer, _) = channel.init()?;
+            Box::pin(self.cache.get(oid, cache::ANY_SIZE, writer)).await?
+        };
+
+        let reader = channel.reader()?;
+        let mut body = pin::pin!(StreamReader::new(reader.stream()?));
+        let mut magic = Vec::with_capacity(MAGIC.len());
+        (&mut body)
+            .take(MAGIC.len() as _)
+            .read_to_end(&mut magic)
+            .await?;
+        let compressed = magic == MAGIC;
+        if compressed {
+            copy(&mut BufReader::new(ZstdDecoder::new(body)), &mut writer).await?;
+        } else {
+            writer.write(&magic).await?;
+            copy(&mut body, &mut writer).await?;
+        }
+        writer.finish().await?;
+        Ok(Source {
+            compressed,
+            source: Box::new(source),
+        })
+    }
+
+    #[tracing::instrument(err, ret)]
+    pub async fn put(
+        &self,
+        oid: &str,
+        size: u64,
+        reader: &channel::Reader<'_>,
+    ) -> anyhow::Result<()> {
+        let level = self.level.map_or(Level::Default, Level::Precise);
+        let mut body = pin::pin!(StreamReader::new(reader.stream()?));
+
+        let mut sample = Vec::new();
+        (&mut body)
+            .take(SAMPLE_SIZE)
+            .read_to_end(&mut sample)
+            .await?;
+        let mut compressed = Vec::new();
+        ZstdEncoder::with_quality(&sample[..], level)
+            .read_to_end(&mut compressed)
+            .await?;
+        if compressed.len() * 10 >= sample.len() * 9 {
+            // already compressed
+            return Box::pin(self.cache.put(oid, size, reader)).await;
+        }
+
+        let mut channel = channel::new_in(size, reader.dir())?;
+        let size = {
+            let (mut writer, _) = channel.init()?;
+            writer.write(MAGIC).await?;
+            let size = copy(
+                &mut BufReader::new(ZstdEncoder::with_quality(
+                    Cursor::new(sample).chain(body),
+                    level,
+                )),
+                &mut writer,
+            )
+            .await?;
+            writer.finish().await?;
+            MAGIC.len() as u64 + size
+        };
+        Box::pin(self.cache.put(oid, size, &channel.reader()?)).await
+    }
+}
+
+async fn copy<R>(reader: &mut R, writer: &mut channel::Writer<'_>) -> anyhow::Result<u64>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut size = 0;
+    loop {
+        let data = reader.fill_buf().await?;
+        if data.is_empty() {
+            break Ok(size);
+        } else {
+            let len = data.len();
+            writer.write(data).await?;
+            reader.consume(len);
+            size += len as u64;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,130 @@
+use crate::{cache, channel};
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+async fn put(cache: &cache::Cache, dir: &Path, body: &[u8]) -> anyhow::Result<String> {
+    let oid = hex::encode(Sha256::digest(body));
+    let mut channel = channel::new_in(body.len() as _, dir)?;
+    let (mut writer, reader) = channel.init()?;
+    writer.write(body).await?;
+    writer.finish().await?;
+    cache.put(&oid, body.len() as _, &reader).await?;
+    Ok(oid)
+}
+
+async fn get(cache: &cache::Cache, dir: &Path, oid: &str, size: u64) -> anyhow::Result<Vec<u8>> {
+    let mut channel = channel::new_in(size, dir)?;
+    let (writer, _) = channel.init()?;
+    cache.get(oid, size, writer).await?;
+    Ok(fs::read(channel.keep()?).await?)
+}
+
+fn path(dir: &Path, oid: &str) -> PathBuf {
+    dir.join(&oid[..2]).join(&oid[2..4]).join(oid)
+}
+
+#[tokio::test]
+async fn test() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let cache_dir = temp_dir.path().join("cache");
+    let cache = cache::Cache::new(serde_json::from_value(serde_json::json!({
+        "zstd": {"cache": {"filesystem": {"dir": cache_dir}}},
+    }))?)
+    .await?;
+
+    let body = b"hello world\n".repeat(1 << 16);
+    let oid = put(&cache, temp_dir.path(), &body).await?;
+    let stored = fs::read(path(&cache_dir, &oid)).await?;
+    anyhow::ensure!(stored.starts_with(super::MAGIC));
+    anyhow::ensure!(stored.len() < body.len());
+    anyhow::ensure!(get(&cache, temp_dir.path(), &oid, body.len() as _).await? == body);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_incompressible() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let cache_dir = temp_dir.path().join("cache");
+    let cache = cache::Cache::new(serde_json::from_value(serde_json::json!({
+        "zstd": {"cache": {"filesystem": {"dir": cache_dir}}},
+    }))?)
+    .await?;
+
+    let mut body = vec![0; 1 << 20];
+    rand::rng().fill(&mut body[..]);
+    let oid = put(&cache, temp_dir.path(), &body).await?;
+    anyhow::ensure!(fs::read(path(&cache_dir, &oid)).await? == body);
+    anyhow::ensure!(get(&cache, temp_dir.path(), &oid, body.len() as _).await? == body);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_uncompressed_entry() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let cache_dir = temp_dir.path().join("cache");
+    let cache = cache::Cache::new(serde_json::from_value(serde_json::json!({
+        "zstd": {"cache": {"filesystem": {"dir": cache_dir}}},
+    }))?)
+    .await?;
+
+    let body = b"hello world\n".repeat(1 << 16);
+    let oid = hex::encode(Sha256::digest(&body));
+    let path = path(&cache_dir, &oid);
+    fs::create_dir_all(path.parent().unwrap()).await?;
+    fs::write(&path, &body).await?;
+    anyhow::ensure!(get(&cache, temp_dir.path(), &oid, body.len() as _).await? == body);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_content_addressed() -> anyhow::Result<()> {
+    anyhow::ensure!(
+        cache::Cache::new(serde_json::from_value(serde_json::json!({
+            "zstd": {"cache": {"sharded": {"nodes": {
+                "a": {"oci": {"registry": "https://registry.example.com", "repository": "foo"}},
+            }}}},
+        }))?)
+        .await
+        .is_err()
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_staging() -> anyhow::Result<()> {
+    // a plugin recording the directories of the paths it is given
+    const PLUGIN: &str = r#"
+while read -r line; do
+    event=$(printf '%s' "$line" | jq -r .event)
+    path=$(printf '%s' "$line" | jq -r .path)
+    case "$event" in
+        get) dirname "$path" >> "$1/dirs" && cp "$1/object" "$path" && echo '{}' ;;
+        put) dirname "$path" >> "$1/dirs" && cp "$path" "$1/object" && echo '{}' ;;
+        *) echo '{}' ;;
+    esac
+done
+"#;
+    let temp_dir = tempfile::tempdir()?;
+    let plugin_dir = temp_dir.path().join("plugin");
+    let staging_dir = temp_dir.path().join("lfs").join("tmp");
+    fs::create_dir_all(&plugin_dir).await?;
+    fs::create_dir_all(&staging_dir).await?;
+    let cache = cache::Cache::new(serde_json::from_value(serde_json::json!({
+        "zstd": {"cache": {"command": {"program": "sh", "args": ["-c", PLUGIN, "sh", plugin_dir]}}},
+    }))?)
+    .await?;
+
+    // next to the channels of the caller, not in the system temp dir
+    let body = b"hello world\n".repeat(1 << 16);
+    let oid = put(&cache, &staging_dir, &body).await?;
+    anyhow::ensure!(get(&cache, &staging_dir, &oid, body.len() as _).await? == body);
+    let dirs = fs::read_to_string(plugin_dir.join("dirs")).await?;
+    let staging_dir = staging_dir.to_str().unwrap_or_default();
+    anyhow::ensure!(dirs == format!("{staging_dir}\n{staging_dir}\n"), "{dirs}");
+    Ok(())
+}
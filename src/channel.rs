@@ -1,8 +1,11 @@
 use bytes::Bytes;
 use futures::Stream;
+use std::cmp;
 use std::fmt;
 use std::io::{self, SeekFrom};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use tempfile::NamedTempFile;
 use tokio::fs::File;
 use tokio::io::AsyncSeekExt;
@@ -17,6 +20,10 @@ where
     Ok(Channel { temp, size })
 }
 
+fn dir(temp: &NamedTempFile) -> &Path {
+    temp.path().parent().unwrap_or(Path::new("."))
+}
+
 pub struct Channel {
     temp: NamedTempFile,
     size: u64,
@@ -25,12 +32,13 @@ pub struct Channel {
 impl Channel {
     pub fn init(&mut self) -> io::Result<(Writer<'_>, Reader<'_>)> {
         self.temp.as_file().set_len(0)?;
-        let (tx, rx) = watch::channel(());
+        let (tx, rx) = watch::channel(0);
         Ok((
             Writer {
                 temp: &self.temp,
                 writer: BufWriter::new(File::from_std(self.temp.reopen()?)),
-                notify: tx,
+                position: 0,
+                notify: Arc::new(tx),
             },
             Reader {
                 temp: &self.temp,
@@ -40,6 +48,17 @@ impl Channel {
         ))
     }
 
+    pub fn reader(&self) -> io::Result<Reader<'_>> {
+        // for a channel whose writer has already finished
+        let size = self.temp.as_file().metadata()?.len();
+        let (_, rx) = watch::channel(size);
+        Ok(Reader {
+            temp: &self.temp,
+            size,
+            notify: rx,
+        })
+    }
+
     pub fn keep(self) -> io::Result<PathBuf> {
         Ok(self.temp.keep()?.1)
     }
@@ -48,7 +67,9 @@ impl Channel {
 pub struct Writer<'a> {
     temp: &'a NamedTempFile,
     writer: BufWriter<File>,
-    notify: watch::Sender<()>,
+    position: u64,
+    // readers never go past this, the end of the contiguous bytes written so far
+    notify: Arc<watch::Sender<u64>>,
 }
 
 impl fmt::Debug for Writer<'_> {
@@ -59,21 +80,155 @@ impl fmt::Debug for Writer<'_> {
     }
 }
 
-impl Writer<'_> {
+impl<'a> Writer<'a> {
     pub async fn write(&mut self, data: &[u8]) -> io::Result<()> {
         self.writer.write_all(data).await?;
-        let _ = self.notify.send(());
+        self.position += data.len() as u64;
+        let position = self.position;
+        self.notify
+            .send_modify(|watermark| *watermark = cmp::max(*watermark, position));
         Ok(())
     }
 
     pub async fn finish(mut self) -> io::Result<()> {
         self.writer.flush().await?;
-        let _ = self.notify.send(());
+        self.notify.send_modify(|_| ());
         Ok(())
     }
 
     pub async fn reset(&mut self) -> io::Result<()> {
-        self.writer.seek(SeekFrom::Start(0)).await?;
+        self.seek(0).await
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    // for the staging files of layers in between, e.g. lfs/tmp rather than /tmp
+    pub fn dir(&self) -> &Path {
+        dir(self.temp)
+    }
+
+    // another writer from the start of the same channel, for a layer that tries its caches in
+    // turn; like seek, what a later attempt rewrites must not change
+    pub fn fork(&self) -> io::Result<Writer<'a>> {
+        Ok(Writer {
+            temp: self.temp,
+            writer: BufWriter::new(File::from_std(self.temp.reopen()?)),
+            position: 0,
+            notify: self.notify.clone(),
+        })
+    }
+
+    // for a channel whose forked writer has already finished
+    pub fn reader(&self) -> Reader<'a> {
+        Reader {
+            temp: self.temp,
+            size: *self.notify.borrow(),
+            notify: self.notify.subscribe(),
+        }
+    }
+
+    // rewriting bytes before the current position must not change them,
+    // since readers may already have consumed them
+    pub async fn seek(&mut self, position: u64) -> io::Result<()> {
+        self.writer.seek(SeekFrom::Start(position)).await?;
+        self.position = position;
+        Ok(())
+    }
+
+    // for a fresh writer, to fill `count` ranges of the object concurrently
+    pub async fn split(self, size: u64, count: u64) -> io::Result<Vec<Segment<'a>>> {
+        let len = size.div_ceil(count.max(1)).max(1);
+        let ranges = (0..size)
+            .step_by(len as _)
+            .map(|start| start..cmp::min(start + len, size))
+            .collect::<Vec<_>>();
+        let shared = Arc::new(Shared {
+            positions: Mutex::new(ranges.iter().map(|range| range.start).collect()),
+            ranges: ranges.clone(),
+            notify: self.notify,
+        });
+        let mut segments = Vec::with_capacity(ranges.len());
+        for (index, range) in ranges.into_iter().enumerate() {
+            let mut file = File::from_std(self.temp.reopen()?);
+            file.seek(SeekFrom::Start(range.start)).await?;
+            segments.push(Segment {
+                temp: self.temp,
+                file,
+                index,
+                position: range.start,
+                range,
+                shared: shared.clone(),
+            });
+        }
+        Ok(segments)
+    }
+}
+
+struct Shared {
+    ranges: Vec<Range<u64>>,
+    positions: Mutex<Vec<u64>>,
+    notify: Arc<watch::Sender<u64>>,
+}
+
+pub struct Segment<'a> {
+    temp: &'a NamedTempFile,
+    file: File,
+    index: usize,
+    range: Range<u64>,
+    position: u64,
+    shared: Arc<Shared>,
+}
+
+impl fmt::Debug for Segment<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Segment")
+            .field("path", &self.temp.path())
+            .field("range", &self.range)
+            .field("position", &self.position)
+            .finish()
+    }
+}
+
+impl Segment<'_> {
+    pub fn range(&self) -> Range<u64> {
+        self.range.clone()
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    pub async fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        if self.position + data.len() as u64 > self.range.end {
+            return Err(io::Error::other("write past the end of the segment"));
+        }
+        self.file.write_all(data).await?;
+        // the bytes must be in the file before readers are allowed to see them
+        self.file.flush().await?;
+        self.position += data.len() as u64;
+
+        let watermark = {
+            let mut positions = self
+                .shared
+                .positions
+                .lock()
+                .map_err(|_| io::ErrorKind::Other)?;
+            positions[self.index] = self.position;
+            self.shared
+                .ranges
+                .iter()
+                .zip(positions.iter())
+                .find(|(range, position)| **position < range.end)
+                .map_or_else(
+                    || self.shared.ranges.last().map_or(0, |range| range.end),
+                    |(_, position)| *position,
+                )
+        };
+        self.shared
+            .notify
+            .send_modify(|value| *value = cmp::max(*value, watermark));
         Ok(())
     }
 }
@@ -81,7 +236,7 @@ impl Writer<'_> {
 pub struct Reader<'a> {
     temp: &'a NamedTempFile,
     size: u64,
-    notify: watch::Receiver<()>,
+    notify: watch::Receiver<u64>,
 }
 
 impl fmt::Debug for Reader<'_> {
@@ -93,6 +248,18 @@ impl fmt::Debug for Reader<'_> {
 }
 
 impl Reader<'_> {
+    pub fn path(&self) -> &Path {
+        self.temp.path()
+    }
+
+    pub fn dir(&self) -> &Path {
+        dir(self.temp)
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
     pub fn stream(
         &self,
     ) -> io::Result<impl Stream<Item = io::Result<Bytes>> + Send + Sync + 'static> {
@@ -106,16 +273,22 @@ impl Reader<'_> {
             move |(mut reader, mut notify, pos)| async move {
                 if pos < size {
                     loop {
-                        let data = reader.fill_buf().await?;
+                        let watermark = *notify.borrow_and_update();
+                        let data = if pos < watermark {
+                            reader.fill_buf().await?
+                        } else {
+                            &[]
+                        };
                         if data.is_empty() {
                             notify
                                 .changed()
                                 .await
                                 .map_err(|_| io::ErrorKind::BrokenPipe)?;
                         } else {
-                            let data = Bytes::copy_from_slice(data);
-                            reader.consume(data.len());
-                            let pos = pos + data.len() as u64;
+                            let len = cmp::min(data.len() as u64, watermark - pos) as usize;
+                            let data = Bytes::copy_from_slice(&data[..len]);
+                            reader.consume(len);
+                            let pos = pos + len as u64;
                             break Ok(Some((data, (reader, notify, pos))));
                         }
                     }
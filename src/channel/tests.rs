@@ -119,3 +119,76 @@ async fn test_large() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_seek() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    let mut channel = super::new_in(11, temp_dir.path())?;
+    let (mut writer, reader) = channel.init()?;
+    let body = tokio::spawn(collect(reader.stream()?));
+    writer.write(b"hello").await?;
+    anyhow::ensure!(writer.position() == 5);
+    // resumed from an earlier offset, overlapping bytes are identical
+    writer.seek(3).await?;
+    writer.write(b"lo world").await?;
+    anyhow::ensure!(writer.position() == 11);
+    writer.finish().await?;
+    let path = channel.keep()?;
+
+    anyhow::ensure!(fs::read(&path).await? == b"hello world");
+    anyhow::ensure!(&*body.await?? == b"hello world");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_split() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    let mut channel = super::new_in(11, temp_dir.path())?;
+    let (writer, reader) = channel.init()?;
+    let body = tokio::spawn(collect(reader.stream()?));
+    let mut segments = writer.split(11, 3).await?;
+    anyhow::ensure!(
+        segments
+            .iter()
+            .map(|segment| segment.range())
+            .eq([0..4, 4..8, 8..11])
+    );
+    // out of order, readers still see the bytes in order
+    segments[2].write(b"rld").await?;
+    segments[1].write(b"o wo").await?;
+    segments[0].write(b"hell").await?;
+    drop(segments);
+    let path = channel.keep()?;
+
+    anyhow::ensure!(fs::read(&path).await? == b"hello world");
+    anyhow::ensure!(&*body.await?? == b"hello world");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_fork() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    let mut channel = super::new_in(11, temp_dir.path())?;
+    let (writer, reader) = channel.init()?;
+    let body = tokio::spawn(collect(reader.stream()?));
+    // a failed attempt, then a complete one from the start
+    let mut fork = writer.fork()?;
+    fork.write(b"hello").await?;
+    drop(fork);
+    let mut fork = writer.fork()?;
+    fork.write(b"hello world").await?;
+    fork.finish().await?;
+    anyhow::ensure!(&*collect(writer.reader().stream()?).await? == b"hello world");
+    writer.finish().await?;
+    let path = channel.keep()?;
+
+    anyhow::ensure!(fs::read(&path).await? == b"hello world");
+    anyhow::ensure!(&*body.await?? == b"hello world");
+
+    Ok(())
+}
@@ -1,10 +1,11 @@
 use crate::misc;
 use clap::Parser;
-use secrecy::SecretString;
+use secrecy::{ExposeSecret, SecretString};
 use std::collections::HashMap;
 use std::fmt::{Debug, Write};
 use std::path::{Path, PathBuf};
 use tokio::process::Command;
+use tokio::sync::Mutex;
 use url::Url;
 
 #[derive(Clone, Debug, Default, Parser)]
@@ -52,7 +53,125 @@ where
         .collect())
 }
 
-#[derive(Debug)]
+// http.* and lfs.* settings, including the http.<url>.* variants
+#[derive(Clone, Debug, Default)]
+pub struct UrlConfig {
+    entries: Vec<UrlConfigEntry>,
+}
+
+#[derive(Clone, Debug)]
+struct UrlConfigEntry {
+    section: String,
+    url: Option<Url>,
+    name: String,
+    value: String,
+}
+
+#[tracing::instrument(err, ret)]
+pub async fn url_config<P>(current_dir: P) -> anyhow::Result<UrlConfig>
+where
+    P: AsRef<Path> + Debug,
+{
+    let stdout = misc::spawn(
+        Command::new("git")
+            .current_dir(current_dir)
+            .arg("config")
+            .arg("--null")
+            .arg("--list"),
+        None,
+    )
+    .await?;
+    Ok(parse_url_config(&String::from_utf8(stdout)?))
+}
+
+// `git config --null --list`
+pub(crate) fn parse_url_config(s: &str) -> UrlConfig {
+    let entries = s
+        .split('\0')
+        .filter_map(|entry| {
+            // a key without `=` is a true boolean, unlike an empty value
+            let (key, value) = entry.split_once('\n').unwrap_or((entry, "true"));
+            let (section, key) = key.split_once('.')?;
+            let section = section.to_lowercase();
+            if section != "http" && section != "lfs" {
+                return None;
+            }
+            // e.g. lfs.transfer.maxretries has no <url> but a dotted name
+            let (url, name) = match key.rsplit_once('.') {
+                Some((url, name)) if let Ok(url) = url.parse() => (Some(url), name),
+                _ => (None, key),
+            };
+            Some(UrlConfigEntry {
+                section,
+                url,
+                name: name.to_lowercase(),
+                value: value.to_string(),
+            })
+        })
+        .collect();
+    UrlConfig { entries }
+}
+
+impl UrlConfig {
+    // like `git config --get-urlmatch`: the most specific matching url wins, then the last one
+    pub fn get(&self, section: &str, name: &str, url: &Url) -> Option<&str> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.section == section && entry.name.eq_ignore_ascii_case(name))
+            .filter_map(|entry| match &entry.url {
+                Some(pattern) => urlmatch(pattern, url).map(|score| (score + 1, entry)),
+                None => Some((0, entry)),
+            })
+            .max_by_key(|(score, _)| *score)
+            .map(|(_, entry)| entry.value.as_str())
+    }
+
+    // ignoring the <url> variants
+    pub fn get_global(&self, section: &str, name: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .rfind(|entry| {
+                entry.section == section
+                    && entry.url.is_none()
+                    && entry.name.eq_ignore_ascii_case(name)
+            })
+            .map(|entry| entry.value.as_str())
+    }
+}
+
+// https://git-scm.com/docs/git-config#Documentation/git-config.txt-httplturlgt
+fn urlmatch(pattern: &Url, url: &Url) -> Option<usize> {
+    if pattern.scheme() != url.scheme()
+        || (!pattern.username().is_empty() && pattern.username() != url.username())
+        || pattern.port_or_known_default() != url.port_or_known_default()
+    {
+        return None;
+    }
+    let pattern_host = pattern.host_str()?.split('.').collect::<Vec<_>>();
+    let url_host = url.host_str()?.split('.').collect::<Vec<_>>();
+    if pattern_host.len() != url_host.len()
+        || pattern_host
+            .iter()
+            .zip(&url_host)
+            .any(|(p, u)| *p != "*" && !p.eq_ignore_ascii_case(u))
+    {
+        return None;
+    }
+    let path = pattern.path().trim_end_matches('/');
+    if path.is_empty()
+        || url.path() == path
+        || url
+            .path()
+            .strip_prefix(path)
+            .is_some_and(|rest| rest.starts_with('/'))
+    {
+        Some(path.len())
+    } else {
+        None
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct Credential {
     pub username: Option<String>,
     pub password: Option<SecretString>,
@@ -62,9 +181,64 @@ pub struct Credential {
 pub async fn credential_fill<P>(current_dir: P, url: &Url) -> anyhow::Result<Credential>
 where
     P: AsRef<Path> + Debug,
+{
+    let stdout = credential_command(current_dir, "fill", url, None).await?;
+
+    // https://git-scm.com/docs/git-credential#IOFMT
+    let stdout = String::from_utf8(stdout)?;
+    let outputs = stdout
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .collect::<HashMap<_, _>>();
+    Ok(Credential {
+        username: outputs.get("username").map(ToString::to_string),
+        password: outputs
+            .get("password")
+            .copied()
+            .map(Box::from)
+            .map(SecretString::new),
+    })
+}
+
+// lets the credential helpers store a credential that worked
+#[tracing::instrument(err, ret)]
+pub async fn credential_approve<P>(
+    current_dir: P,
+    url: &Url,
+    credential: &Credential,
+) -> anyhow::Result<()>
+where
+    P: AsRef<Path> + Debug,
+{
+    credential_command(current_dir, "approve", url, Some(credential)).await?;
+    Ok(())
+}
+
+// lets the credential helpers erase a credential that was refused
+#[tracing::instrument(err, ret)]
+pub async fn credential_reject<P>(
+    current_dir: P,
+    url: &Url,
+    credential: &Credential,
+) -> anyhow::Result<()>
+where
+    P: AsRef<Path> + Debug,
+{
+    credential_command(current_dir, "reject", url, Some(credential)).await?;
+    Ok(())
+}
+
+async fn credential_command<P>(
+    current_dir: P,
+    action: &str,
+    url: &Url,
+    credential: Option<&Credential>,
+) -> anyhow::Result<Vec<u8>>
+where
+    P: AsRef<Path>,
 {
     // https://git-scm.com/docs/git-credential#IOFMT
-    let inputs = [
+    let mut inputs = [
         ("protocol", url.scheme()),
         ("host", url.authority()),
         ("path", url.path().trim_start_matches('/')),
@@ -74,30 +248,69 @@ where
         let _ = writeln!(inputs, "{key}={value}");
         inputs
     });
+    if let Some(credential) = credential {
+        if let Some(username) = &credential.username {
+            let _ = writeln!(inputs, "username={username}");
+        }
+        if let Some(password) = &credential.password {
+            let _ = writeln!(inputs, "password={}", password.expose_secret());
+        }
+    }
 
-    let stdout = misc::spawn(
+    misc::spawn(
         Command::new("git")
             .current_dir(current_dir)
             .arg("credential")
-            .arg("fill"),
+            .arg(action),
         Some(inputs.as_bytes()),
     )
-    .await?;
+    .await
+}
 
-    // https://git-scm.com/docs/git-credential#IOFMT
-    let stdout = String::from_utf8(stdout)?;
-    let outputs = stdout
-        .lines()
-        .filter_map(|line| line.split_once('='))
-        .collect::<HashMap<_, _>>();
-    Ok(Credential {
-        username: outputs.get("username").map(ToString::to_string),
-        password: outputs
-            .get("password")
-            .copied()
-            .map(Box::from)
-            .map(SecretString::new),
-    })
+// asks the credential helpers (and so the user) at most once per url in a session
+#[derive(Debug)]
+pub struct Credentials {
+    current_dir: PathBuf,
+    // and whether it has been approved
+    cache: Mutex<HashMap<Url, (Credential, bool)>>,
+}
+
+impl Credentials {
+    pub fn new(current_dir: PathBuf) -> Self {
+        Self {
+            current_dir,
+            cache: Mutex::default(),
+        }
+    }
+
+    pub async fn fill(&self, url: &Url) -> anyhow::Result<Credential> {
+        let mut cache = self.cache.lock().await;
+        if let Some((credential, _)) = cache.get(url) {
+            return Ok(credential.clone());
+        }
+        let credential = credential_fill(&self.current_dir, url).await?;
+        cache.insert(url.clone(), (credential.clone(), false));
+        Ok(credential)
+    }
+
+    pub async fn approve(&self, url: &Url) -> anyhow::Result<()> {
+        let mut cache = self.cache.lock().await;
+        if let Some((credential, approved)) = cache.get_mut(url)
+            && !*approved
+        {
+            credential_approve(&self.current_dir, url, credential).await?;
+            *approved = true;
+        }
+        Ok(())
+    }
+
+    // the next fill asks again
+    pub async fn reject(&self, url: &Url) -> anyhow::Result<()> {
+        if let Some((credential, _)) = self.cache.lock().await.remove(url) {
+            credential_reject(&self.current_dir, url, &credential).await?;
+        }
+        Ok(())
+    }
 }
 
 #[tracing::instrument(err, ret)]
@@ -166,3 +379,6 @@ where
     .await?;
     Ok(String::from_utf8(stdout)?.trim().parse()?)
 }
+
+#[cfg(test)]
+mod tests;
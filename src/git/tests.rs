@@ -0,0 +1,31 @@
+use super::parse_url_config;
+
+#[test]
+fn test_url_config() -> anyhow::Result<()> {
+    let config = parse_url_config(concat!(
+        "http.proxy\nhttp://proxy:3128\0",
+        "http.https://*.example.com.proxy\nhttp://wildcard:3128\0",
+        "http.https://git.example.com/org.proxy\nhttp://org:3128\0",
+        "http.https://git.example.com/org/private.proxy\n\0",
+        "lfs.customtransfer.git-lfs-cache.path\n/usr/bin/git-lfs-cache\0",
+        "lfs.transfer.maxretries\n3\0",
+    ));
+    let get = |url: &str| -> anyhow::Result<Option<&str>> {
+        Ok(config.get("http", "proxy", &url.parse()?))
+    };
+
+    anyhow::ensure!(get("https://example.org/")? == Some("http://proxy:3128"));
+    anyhow::ensure!(get("http://git.example.com/org")? == Some("http://proxy:3128"));
+    anyhow::ensure!(get("https://git.example.com/")? == Some("http://wildcard:3128"));
+    anyhow::ensure!(get("https://a.b.example.com/")? == Some("http://proxy:3128"));
+    anyhow::ensure!(get("https://git.example.com/org/repo")? == Some("http://org:3128"));
+    anyhow::ensure!(get("https://git.example.com/organization")? == Some("http://wildcard:3128"));
+    anyhow::ensure!(get("https://git.example.com/org/private/info/lfs")? == Some(""));
+    anyhow::ensure!(
+        config
+            .get("lfs", "proxy", &"https://example.org/".parse()?)
+            .is_none()
+    );
+    anyhow::ensure!(config.get_global("lfs", "transfer.maxretries") == Some("3"));
+    Ok(())
+}
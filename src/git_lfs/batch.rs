@@ -2,14 +2,17 @@
 
 use super::{Error, Operation};
 use crate::misc;
-use http::{HeaderMap, header};
-use http_body_util::{BodyExt, Full};
+use bytes::Bytes;
+use futures::TryFutureExt;
+use http::{HeaderMap, HeaderValue, Method, header};
+use http_body_util::BodyExt;
 use serde::{Deserialize, Serialize};
 use url::Url;
 
 #[tracing::instrument(err, ret)]
 pub async fn batch(
     client: &misc::Client,
+    retry: &misc::Retry,
     href: &Url,
     header: &HeaderMap,
     request: &Request<'_>,
@@ -18,40 +21,49 @@ pub async fn batch(
     misc::path_segments_mut(&mut href)?
         .push("objects")
         .push("batch");
-    let builder = http::Request::post(href.as_ref());
-    let builder = header.iter().fold(builder, |builder, (name, value)| {
-        builder.header(name, value)
-    });
-    let request = builder
-        .header(header::ACCEPT, "application/vnd.git-lfs+json")
-        .header(header::CONTENT_TYPE, "application/vnd.git-lfs+json")
-        .body(
-            Full::from(serde_json::to_vec(&request)?)
-                .map_err(Box::from)
-                .boxed_unsync(),
-        )?;
-    let response = client.request(request).await?;
-    let (parts, body) = response.into_parts();
-    let body = body.collect().await?.to_bytes();
-    if parts.status.is_success() {
-        Ok(serde_json::from_slice(&body)?)
-    } else {
-        #[derive(Deserialize)]
-        struct B {
-            message: String,
-        }
+    let body = Bytes::from(serde_json::to_vec(&request)?);
+    retry
+        .run(|| async {
+            let mut headers = header.clone();
+            headers.insert(
+                header::ACCEPT,
+                HeaderValue::from_static("application/vnd.git-lfs+json"),
+            );
+            headers.insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/vnd.git-lfs+json"),
+            );
+            let response =
+                misc::request(client, Method::POST, href.clone(), headers, body.clone()).await?;
+            let (parts, body) = response.into_parts();
+            let body = body
+                .collect()
+                .map_err(misc::backoff_transient)
+                .await?
+                .to_bytes();
+            if parts.status.is_success() {
+                serde_json::from_slice(&body).map_err(misc::backoff_permanent)
+            } else {
+                #[derive(Deserialize)]
+                struct B {
+                    message: String,
+                }
 
-        let message = if let Ok(B { message }) = serde_json::from_slice(&body) {
-            message
-        } else {
-            format!("{body:?}")
-        };
-        Err(Error {
-            code: parts.status,
-            message,
-        }
-        .into())
-    }
+                let message = if let Ok(B { message }) = serde_json::from_slice(&body) {
+                    message
+                } else {
+                    format!("{body:?}")
+                };
+                Err(misc::backoff_error(
+                    Error {
+                        code: parts.status,
+                        message,
+                    },
+                    &parts.headers,
+                ))
+            }
+        })
+        .await
 }
 
 #[derive(Debug, Serialize)]
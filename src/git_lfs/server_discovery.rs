@@ -2,15 +2,18 @@
 
 use super::Operation;
 use crate::{git, misc};
+use chrono::{DateTime, Utc};
 use futures::TryFutureExt;
 use headers::{Authorization, HeaderMapExt};
 use http::{HeaderMap, HeaderName, HeaderValue, header};
 use secrecy::ExposeSecret;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::cell::Cell;
+use std::collections::HashMap;
 use std::env;
 use std::fmt::Debug;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use tokio::fs;
 use tokio::process::Command;
 use url::Url;
 
@@ -23,7 +26,7 @@ pub async fn server_discovery<P>(
     current_dir: P,
     operation: Operation,
     remote: &str,
-    authorization: bool,
+    authorization: Option<&git::Credentials>,
 ) -> anyhow::Result<Response>
 where
     P: AsRef<Path> + Debug,
@@ -74,15 +77,17 @@ where
                     ))
                 }));
             }
-            if authorization
+            let mut credential = None;
+            if let Some(credentials) = authorization
                 && !header.contains_key(header::AUTHORIZATION)
                 && let Ok(git::Credential {
                     username: Some(username),
                     password: Some(password),
                     ..
-                }) = git::credential_fill(current_dir, &url).await
+                }) = credentials.fill(&url).await
             {
                 header.typed_insert(Authorization::basic(&username, password.expose_secret()));
+                credential = Some(url.clone());
             }
 
             let href = if custom {
@@ -94,10 +99,36 @@ where
                 href
             };
 
-            Ok(Response { href, header })
+            Ok(Response {
+                href,
+                header,
+                credential,
+                expires_in: None,
+                expires_at: None,
+                authenticated: None,
+            })
         }
         "ssh" => {
-            if authorization {
+            if authorization.is_some() {
+                let operation = match operation {
+                    Operation::Upload => "upload",
+                    Operation::Download => "download",
+                };
+                let key = format!("{operation} {url}");
+                let path = authenticated_path(current_dir).await?;
+                let mut authenticated = load_authenticated(&path).await?;
+                if let Some(entry) = authenticated.get(&key) {
+                    tracing::info!(expires_at = %entry.expires_at, "reusing git-lfs-authenticate");
+                    return Ok(Response {
+                        href: entry.href.clone(),
+                        header: entry.header.clone(),
+                        credential: None,
+                        expires_in: None,
+                        expires_at: Some(entry.expires_at),
+                        authenticated: Some(key),
+                    });
+                }
+
                 let ssh_command = GIT_SSH_COMMAND
                     .get()
                     .map(str::to_owned)
@@ -123,12 +154,27 @@ where
                     )
                     .arg("git-lfs-authenticate")
                     .arg(url.path())
-                    .arg(match operation {
-                        Operation::Upload => "upload",
-                        Operation::Download => "download",
-                    });
+                    .arg(operation);
                 let stdout = misc::spawn(&mut command, None).await?;
-                Ok(serde_json::from_slice(&stdout)?)
+                let mut response = serde_json::from_slice::<Response>(&stdout)?;
+                // without an expiry, the result is only good for this invocation
+                if let Some(expires_at) = response.expires_at.or_else(|| {
+                    response
+                        .expires_in
+                        .map(|expires_in| Utc::now() + chrono::Duration::seconds(expires_in))
+                }) {
+                    authenticated.insert(
+                        key.clone(),
+                        Authenticated {
+                            href: response.href.clone(),
+                            header: response.header.clone(),
+                            expires_at,
+                        },
+                    );
+                    store_authenticated(&path, &authenticated).await?;
+                    response.authenticated = Some(key);
+                }
+                Ok(response)
             } else {
                 let href = if custom {
                     url
@@ -149,6 +195,10 @@ where
                 Ok(Response {
                     href,
                     header: HeaderMap::new(),
+                    credential: None,
+                    expires_in: None,
+                    expires_at: None,
+                    authenticated: None,
                 })
             }
         }
@@ -161,6 +211,121 @@ pub struct Response {
     pub href: Url,
     #[serde(with = "http_serde::header_map")]
     pub header: HeaderMap,
+    // the url whose credential is in `header`, to approve or reject
+    #[serde(skip)]
+    pub credential: Option<Url>,
+    // seconds
+    #[serde(default)]
+    pub expires_in: Option<i64>,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    // the persisted git-lfs-authenticate result, to forget when refused
+    #[serde(skip)]
+    pub authenticated: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Authenticated {
+    href: Url,
+    #[serde(with = "http_serde::header_map")]
+    header: HeaderMap,
+    expires_at: DateTime<Utc>,
+}
+
+// renewed a little before the server says, like git-lfs
+const EXPIRY_MARGIN: chrono::Duration = chrono::Duration::seconds(5);
+
+async fn authenticated_path<P>(current_dir: P) -> anyhow::Result<PathBuf>
+where
+    P: AsRef<Path> + Debug,
+{
+    Ok(git::rev_parse_absolute_git_dir(current_dir)
+        .await?
+        .join(env!("CARGO_PKG_NAME").trim_start_matches("git-"))
+        .join("ssh.json"))
+}
+
+async fn load_authenticated(path: &Path) -> anyhow::Result<HashMap<String, Authenticated>> {
+    let mut entries = HashMap::new();
+    if fs::try_exists(path).await? {
+        entries = serde_json::from_slice::<HashMap<String, Authenticated>>(&fs::read(path).await?)?;
+        entries.retain(|_, entry| entry.expires_at - EXPIRY_MARGIN > Utc::now());
+    }
+    Ok(entries)
+}
+
+async fn store_authenticated(
+    path: &Path,
+    entries: &HashMap<String, Authenticated>,
+) -> anyhow::Result<()> {
+    let dir = path
+        .parent()
+        .ok_or_else(|| anyhow::format_err!("missing parent"))?;
+    fs::create_dir_all(dir).await?;
+    // the headers are credentials, temporary files are only readable by their owner
+    let temp = tempfile::NamedTempFile::new_in(dir)?;
+    fs::write(temp.path(), serde_json::to_vec(entries)?).await?;
+    temp.persist(path)?;
+    Ok(())
+}
+
+// after a 401, the next server discovery runs git-lfs-authenticate again
+#[tracing::instrument(err, ret)]
+pub async fn forget<P>(current_dir: P, authenticated: &str) -> anyhow::Result<()>
+where
+    P: AsRef<Path> + Debug,
+{
+    let path = authenticated_path(current_dir).await?;
+    let mut entries = load_authenticated(&path).await?;
+    if entries.remove(authenticated).is_some() {
+        store_authenticated(&path, &entries).await?;
+    }
+    Ok(())
+}
+
+// lfs.<url>.access, recorded by git-lfs once basic authentication worked for an endpoint
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Access {
+    None,
+    Basic,
+    Negotiate,
+}
+
+pub fn access(href: &Url) -> Access {
+    access_with(misc::config(), href)
+}
+
+fn access_with(config: &git::UrlConfig, href: &Url) -> Access {
+    match config.get("lfs", "access", href) {
+        Some(value) if value.eq_ignore_ascii_case("basic") => Access::Basic,
+        Some(value) if value.eq_ignore_ascii_case("negotiate") => {
+            tracing::warn!(%href, "negotiate access is not supported, requests go unauthenticated");
+            Access::Negotiate
+        }
+        _ => Access::None,
+    }
+}
+
+#[tracing::instrument(err, ret)]
+pub async fn set_access<P>(current_dir: P, href: &Url, access: Access) -> anyhow::Result<()>
+where
+    P: AsRef<Path> + Debug,
+{
+    let value = match access {
+        Access::None => "none",
+        Access::Basic => "basic",
+        Access::Negotiate => "negotiate",
+    };
+    git::config(
+        current_dir,
+        &git::Location {
+            local: true,
+            ..git::Location::default()
+        },
+        |command| command.arg(format!("lfs.{href}.access")).arg(value),
+    )
+    .await?;
+    Ok(())
 }
 
 // https://github.com/git-lfs/git-lfs/blob/main/docs/api/server-discovery.md#custom-configuration
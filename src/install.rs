@@ -1,4 +1,4 @@
-use crate::{cache, git};
+use crate::{cache, git, transfer_agent};
 use clap::Parser;
 use std::borrow::{Borrow, Cow};
 use std::env;
@@ -9,6 +9,14 @@ pub struct Args {
     location: git::Location,
     #[clap(long)]
     cache: Option<cache::Args>,
+    #[clap(long)]
+    hedge: Option<transfer_agent::Hedge>,
+    #[clap(long)]
+    circuit_breaker: Option<transfer_agent::CircuitBreaker>,
+    #[clap(long)]
+    negative_cache: Option<transfer_agent::NegativeCache>,
+    #[clap(long)]
+    segments: Option<u64>,
 }
 
 pub async fn main(args: Args) -> anyhow::Result<()> {
@@ -20,6 +28,22 @@ pub async fn main(args: Args) -> anyhow::Result<()> {
         transfer_agent.push(Cow::Borrowed("--cache"));
         transfer_agent.push(Cow::Owned(serde_json::to_string(cache)?));
     }
+    if let Some(hedge) = &args.hedge {
+        transfer_agent.push(Cow::Borrowed("--hedge"));
+        transfer_agent.push(Cow::Owned(serde_json::to_string(hedge)?));
+    }
+    if let Some(circuit_breaker) = &args.circuit_breaker {
+        transfer_agent.push(Cow::Borrowed("--circuit-breaker"));
+        transfer_agent.push(Cow::Owned(serde_json::to_string(circuit_breaker)?));
+    }
+    if let Some(negative_cache) = &args.negative_cache {
+        transfer_agent.push(Cow::Borrowed("--negative-cache"));
+        transfer_agent.push(Cow::Owned(serde_json::to_string(negative_cache)?));
+    }
+    if let Some(segments) = args.segments {
+        transfer_agent.push(Cow::Borrowed("--segments"));
+        transfer_agent.push(Cow::Owned(segments.to_string()));
+    }
     let transfer_agent = shlex::Quoter::new().join(transfer_agent.iter().map(Borrow::borrow))?;
 
     git::config(&current_dir, &args.location, |command| {
@@ -6,7 +6,10 @@ mod install;
 mod jsonl;
 mod logs;
 mod misc;
+mod proxy;
 mod stats;
+mod timeout;
+mod tls;
 mod transfer_agent;
 
 use clap::Parser;
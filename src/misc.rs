@@ -1,37 +1,67 @@
+use crate::{cache, channel, git, git_lfs, proxy, timeout, tls};
+use backoff::backoff::Backoff;
 use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::TryFutureExt;
+use headers::{ContentRange, HeaderMapExt};
+use http::{HeaderMap, Method, Request, Response, StatusCode, header};
 use http_body_util::combinators::UnsyncBoxBody;
-use hyper_rustls::ConfigBuilderExt;
+use http_body_util::{BodyExt, Empty, Full};
+use hyper::body::Incoming;
+use std::cmp;
+use std::ops::Range;
 use std::process::Stdio;
-use std::sync::Arc;
+use std::sync::OnceLock;
+use std::time::Duration;
 use tokio::process::Command;
+use tokio::sync::Mutex;
 use url::{PathSegmentsMut, Url};
 
-pub type Connector =
-    hyper_rustls::HttpsConnector<hyper_util::client::legacy::connect::HttpConnector>;
-pub type Client<B = UnsyncBoxBody<Bytes, Box<dyn std::error::Error + Send + Sync>>> =
-    hyper_util::client::legacy::Client<Connector, B>;
+const MAX_REDIRECTS: usize = 8;
+
+pub type Connector = tls::Connector;
+pub type Body = UnsyncBoxBody<Bytes, Box<dyn std::error::Error + Send + Sync>>;
+pub type Client<B = Body> = hyper_util::client::legacy::Client<Connector, B>;
 pub fn client<B>() -> anyhow::Result<Client<B>>
 where
     B: http_body::Body + Send,
     B::Data: Send,
 {
-    let tls_config = rustls::ClientConfig::builder_with_provider(Arc::new(
-        rustls::crypto::ring::default_provider(),
-    ))
-    .with_safe_default_protocol_versions()?
-    .with_native_roots()?
-    .with_no_client_auth();
-    let connector = hyper_rustls::HttpsConnectorBuilder::new()
-        .with_tls_config(tls_config)
-        .https_or_http()
-        .enable_http1()
-        .enable_http2()
-        .build();
+    client_with(None, None)
+}
+
+// `tls` and `timeout` take precedence over git's http.ssl* and lfs.*timeout settings
+pub fn client_with<B>(
+    tls: Option<tls::Args>,
+    timeout: Option<timeout::Args>,
+) -> anyhow::Result<Client<B>>
+where
+    B: http_body::Body + Send,
+    B::Data: Send,
+{
     let client = hyper_util::client::legacy::Client::builder(hyper_util::rt::TokioExecutor::new())
-        .build(connector);
+        .build(connector(tls, timeout)?);
     Ok(client)
 }
 
+static CONFIG: OnceLock<git::UrlConfig> = OnceLock::new();
+
+// git's http.* and lfs.* settings, for every client built afterwards
+pub fn init(config: git::UrlConfig) {
+    let _ = CONFIG.set(config);
+}
+
+pub fn config() -> &'static git::UrlConfig {
+    CONFIG.get_or_init(git::UrlConfig::default)
+}
+
+pub fn connector(
+    tls: Option<tls::Args>,
+    timeout: Option<timeout::Args>,
+) -> anyhow::Result<Connector> {
+    Ok(tls::Connector::new(tls, timeout))
+}
+
 pub async fn spawn(command: &mut Command, stdin: Option<&[u8]>) -> anyhow::Result<Vec<u8>> {
     command
         .stdin(Stdio::piped())
@@ -61,6 +91,98 @@ pub async fn spawn(command: &mut Command, stdin: Option<&[u8]>) -> anyhow::Resul
     }
 }
 
+// follows redirects like git-lfs: never from https to http, no Authorization for another
+// origin, and 301/302/303 turn a POST into a GET
+pub async fn request(
+    client: &Client,
+    method: Method,
+    url: Url,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response<Incoming>, backoff::Error<anyhow::Error>> {
+    request_with(client, method, url, headers, || {
+        Ok(Full::new(body.clone()).map_err(Box::from).boxed_unsync())
+    })
+    .await
+}
+
+// `request` for bodies which are built again for every hop, e.g. streamed from a channel
+pub async fn request_with<F>(
+    client: &Client,
+    mut method: Method,
+    mut url: Url,
+    mut headers: HeaderMap,
+    body: F,
+) -> Result<Response<Incoming>, backoff::Error<anyhow::Error>>
+where
+    F: Fn() -> anyhow::Result<Body>,
+{
+    let mut chain = vec![url.clone()];
+    let mut empty = false;
+    loop {
+        let mut builder = Request::builder().method(method.clone()).uri(url.as_str());
+        if let Some(builder_headers) = builder.headers_mut() {
+            builder_headers.extend(headers.clone());
+            if let Some(authorization) = proxy::authorization(&url) {
+                builder_headers.insert(header::PROXY_AUTHORIZATION, authorization);
+            }
+        }
+        let body = if empty {
+            Empty::new().map_err(Box::from).boxed_unsync()
+        } else {
+            body().map_err(backoff_permanent)?
+        };
+        let request = builder.body(body).map_err(backoff_permanent)?;
+        let response = client.request(request).map_err(backoff_transient).await?;
+
+        let status = response.status();
+        let location = match status {
+            StatusCode::MOVED_PERMANENTLY
+            | StatusCode::FOUND
+            | StatusCode::SEE_OTHER
+            | StatusCode::TEMPORARY_REDIRECT
+            | StatusCode::PERMANENT_REDIRECT => response.headers().get(header::LOCATION),
+            _ => None,
+        };
+        let Some(location) = location else {
+            if chain.len() > 1 {
+                tracing::info!(?chain, "redirected");
+            }
+            return Ok(response);
+        };
+        if chain.len() > MAX_REDIRECTS {
+            return Err(backoff_permanent(anyhow::format_err!(
+                "too many redirects: {chain:?}"
+            )));
+        }
+        let location = location
+            .to_str()
+            .map_err(backoff_permanent)
+            .and_then(|location| url.join(location).map_err(backoff_permanent))?;
+        if url.scheme() == "https" && location.scheme() == "http" {
+            return Err(backoff_permanent(anyhow::format_err!(
+                "refusing insecure redirect: {url} -> {location}"
+            )));
+        }
+        // e.g. a pre-signed URL of object storage
+        if location.origin() != url.origin() {
+            headers.remove(header::AUTHORIZATION);
+        }
+        if status != StatusCode::TEMPORARY_REDIRECT
+            && status != StatusCode::PERMANENT_REDIRECT
+            && method != Method::GET
+            && method != Method::HEAD
+        {
+            method = Method::GET;
+            empty = true;
+            headers.remove(header::CONTENT_TYPE);
+            headers.remove(header::CONTENT_LENGTH);
+        }
+        url = location;
+        chain.push(url.clone());
+    }
+}
+
 pub fn path_segments_mut(url: &mut Url) -> anyhow::Result<PathSegmentsMut<'_>> {
     let mut path_segments = url
         .path_segments_mut()
@@ -82,3 +204,319 @@ where
 {
     backoff::Error::transient(anyhow::Error::from(e))
 }
+
+pub fn backoff_status(
+    code: StatusCode,
+    headers: &HeaderMap,
+    body: Bytes,
+) -> backoff::Error<anyhow::Error> {
+    backoff_error(
+        git_lfs::Error {
+            code,
+            message: format!("{body:?}"),
+        },
+        headers,
+    )
+}
+
+// transient or permanent by status, waiting as long as the server asks
+pub fn backoff_error(e: git_lfs::Error, headers: &HeaderMap) -> backoff::Error<anyhow::Error> {
+    let delay = match e.code {
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => retry_after(headers),
+        _ => None,
+    };
+    if !transient_status(e.code) {
+        backoff_permanent(e)
+    } else if let Some(delay) = delay {
+        backoff::Error::retry_after(anyhow::Error::from(e), delay)
+    } else {
+        backoff_transient(e)
+    }
+}
+
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(header::RETRY_AFTER)?.to_str().ok()?;
+    if let Ok(seconds) = value.parse() {
+        Some(Duration::from_secs(seconds))
+    } else {
+        let date = DateTime::parse_from_rfc2822(value).ok()?;
+        Some((date.to_utc() - Utc::now()).to_std().unwrap_or_default())
+    }
+}
+
+pub fn transient_status(code: StatusCode) -> bool {
+    code == StatusCode::REQUEST_TIMEOUT
+        || code == StatusCode::TOO_MANY_REQUESTS
+        || code.is_server_error()
+}
+
+// lfs.transfer.maxretries and lfs.transfer.maxretrydelay
+#[derive(Clone, Debug)]
+pub struct Retry {
+    pub max_retries: u64,
+    pub max_delay: Duration,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Self {
+            max_retries: 8,
+            max_delay: Duration::from_secs(10),
+        }
+    }
+}
+
+impl Retry {
+    // from the git config given to `init`
+    pub fn config() -> anyhow::Result<Self> {
+        let default = Self::default();
+        let get = |name, default| -> anyhow::Result<u64> {
+            Ok(config()
+                .get_global("lfs", name)
+                .map(str::parse)
+                .transpose()?
+                .unwrap_or(default))
+        };
+        Ok(Self {
+            max_retries: get("transfer.maxretries", default.max_retries)?,
+            max_delay: Duration::from_secs(get(
+                "transfer.maxretrydelay",
+                default.max_delay.as_secs(),
+            )?),
+        })
+    }
+
+    // exponential backoff up to max_delay, unless the error carries a Retry-After
+    pub async fn run<T, F, Fut>(&self, mut operation: F) -> anyhow::Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, backoff::Error<anyhow::Error>>>,
+    {
+        let mut backoff = backoff::ExponentialBackoff {
+            max_interval: self.max_delay,
+            max_elapsed_time: None,
+            ..Default::default()
+        };
+        let mut retries = 0;
+        loop {
+            match operation().await {
+                Ok(value) => return Ok(value),
+                Err(backoff::Error::Permanent(e)) => return Err(e),
+                Err(backoff::Error::Transient { err, retry_after }) => {
+                    if retries >= self.max_retries {
+                        return Err(err);
+                    }
+                    retries += 1;
+                    let delay = retry_after
+                        .or_else(|| backoff.next_backoff())
+                        .unwrap_or(self.max_delay);
+                    tracing::warn!(retries, ?delay, error = ?err);
+                    tokio::time::sleep(delay).await;
+                }
+            }
+        }
+    }
+}
+
+pub const SEGMENTS: u64 = 4;
+// objects smaller than this are not worth splitting
+const MIN_SEGMENT_SIZE: u64 = 1 << 26;
+
+// GET into writer, resuming after transient errors, in up to `segments` concurrent ranges
+// when the server honors them; `send` adds a Range header when given one
+pub async fn get<F, Fut, B>(
+    send: F,
+    size: u64,
+    segments: u64,
+    retry: &Retry,
+    writer: channel::Writer<'_>,
+) -> anyhow::Result<()>
+where
+    F: Fn(Option<Range<u64>>) -> Fut,
+    Fut: Future<Output = Result<Response<B>, backoff::Error<anyhow::Error>>>,
+    B: http_body::Body<Data = Bytes> + Unpin,
+    B::Error: std::error::Error + Send + Sync + 'static,
+{
+    get_segmented(
+        send,
+        size,
+        cmp::min(segments, size / MIN_SEGMENT_SIZE),
+        retry,
+        writer,
+    )
+    .await
+}
+
+async fn get_segmented<F, Fut, B>(
+    send: F,
+    size: u64,
+    segments: u64,
+    retry: &Retry,
+    writer: channel::Writer<'_>,
+) -> anyhow::Result<()>
+where
+    F: Fn(Option<Range<u64>>) -> Fut,
+    Fut: Future<Output = Result<Response<B>, backoff::Error<anyhow::Error>>>,
+    B: http_body::Body<Data = Bytes> + Unpin,
+    B::Error: std::error::Error + Send + Sync + 'static,
+{
+    if segments <= 1 || size == cache::ANY_SIZE {
+        return get_sequential(&send, size, retry, writer, None).await;
+    }
+
+    // the first segment tells whether the server honors ranges
+    let first = 0..size.div_ceil(segments);
+    let response = retry
+        .run(|| async { success(send(Some(first.clone())).await?).await })
+        .await?;
+    let total = response
+        .headers()
+        .typed_get::<ContentRange>()
+        .and_then(|content_range| content_range.bytes_len());
+    if response.status() == StatusCode::PARTIAL_CONTENT && total == Some(size) {
+        let mut response = Some(response);
+        futures::future::try_join_all(
+            writer
+                .split(size, segments)
+                .await?
+                .into_iter()
+                .map(|segment| get_segment(&send, retry, segment, response.take())),
+        )
+        .await?;
+        Ok(())
+    } else {
+        get_sequential(&send, size, retry, writer, Some(response)).await
+    }
+}
+
+async fn get_sequential<F, Fut, B>(
+    send: &F,
+    size: u64,
+    retry: &Retry,
+    writer: channel::Writer<'_>,
+    response: Option<Response<B>>,
+) -> anyhow::Result<()>
+where
+    F: Fn(Option<Range<u64>>) -> Fut,
+    Fut: Future<Output = Result<Response<B>, backoff::Error<anyhow::Error>>>,
+    B: http_body::Body<Data = Bytes> + Unpin,
+    B::Error: std::error::Error + Send + Sync + 'static,
+{
+    let writer = Mutex::new(writer);
+    let response = Mutex::new(response);
+    retry
+        .run(|| async {
+            let mut writer = writer.lock().await;
+            let response = match response.lock().await.take() {
+                Some(response) => response,
+                None => {
+                    let range = (writer.position() > 0).then(|| writer.position()..size);
+                    success(send(range).await?).await?
+                }
+            };
+            let (parts, mut body) = response.into_parts();
+            // the stored length, when the caller did not know it
+            let end = parts
+                .headers
+                .typed_get::<ContentRange>()
+                .and_then(|content_range| content_range.bytes_len())
+                .unwrap_or(size);
+            if parts.status == StatusCode::PARTIAL_CONTENT {
+                let start = content_range_start(&parts.headers)?;
+                if start > writer.position() {
+                    return Err(backoff_permanent(anyhow::format_err!(
+                        "content-range starts at {start}, expected {}",
+                        writer.position(),
+                    )));
+                }
+                writer.seek(start).map_err(backoff_permanent).await?;
+            } else {
+                writer.reset().map_err(backoff_permanent).await?;
+            }
+            while let Some(frame) = body.frame().await.transpose().map_err(backoff_transient)? {
+                if let Ok(data) = frame.into_data() {
+                    writer.write(&data).map_err(backoff_permanent).await?;
+                }
+            }
+            if parts.status == StatusCode::PARTIAL_CONTENT && writer.position() < end {
+                // ask for the rest
+                Err(backoff_transient(anyhow::format_err!("partial content")))
+            } else {
+                Ok(())
+            }
+        })
+        .await?;
+    writer.into_inner().finish().await?;
+    Ok(())
+}
+
+async fn get_segment<F, Fut, B>(
+    send: &F,
+    retry: &Retry,
+    segment: channel::Segment<'_>,
+    response: Option<Response<B>>,
+) -> anyhow::Result<()>
+where
+    F: Fn(Option<Range<u64>>) -> Fut,
+    Fut: Future<Output = Result<Response<B>, backoff::Error<anyhow::Error>>>,
+    B: http_body::Body<Data = Bytes> + Unpin,
+    B::Error: std::error::Error + Send + Sync + 'static,
+{
+    let segment = Mutex::new(segment);
+    let response = Mutex::new(response);
+    retry
+        .run(|| async {
+            let mut segment = segment.lock().await;
+            if segment.position() == segment.range().end {
+                return Ok(());
+            }
+            let response = match response.lock().await.take() {
+                Some(response) => response,
+                None => success(send(Some(segment.position()..segment.range().end)).await?).await?,
+            };
+            let (parts, mut body) = response.into_parts();
+            if parts.status != StatusCode::PARTIAL_CONTENT
+                || content_range_start(&parts.headers)? != segment.position()
+            {
+                return Err(backoff_permanent(anyhow::format_err!(
+                    "unexpected response to a range request: {parts:?}"
+                )));
+            }
+            while let Some(frame) = body.frame().await.transpose().map_err(backoff_transient)? {
+                if let Ok(data) = frame.into_data() {
+                    segment.write(&data).map_err(backoff_permanent).await?;
+                }
+            }
+            if segment.position() < segment.range().end {
+                Err(backoff_transient(anyhow::format_err!("partial content")))
+            } else {
+                Ok(())
+            }
+        })
+        .await
+}
+
+async fn success<B>(response: Response<B>) -> Result<Response<B>, backoff::Error<anyhow::Error>>
+where
+    B: http_body::Body<Data = Bytes>,
+    B::Error: std::error::Error + Send + Sync + 'static,
+{
+    if response.status().is_success() {
+        Ok(response)
+    } else {
+        let (parts, body) = response.into_parts();
+        let body = body.collect().map_err(backoff_transient).await?.to_bytes();
+        Err(backoff_status(parts.status, &parts.headers, body))
+    }
+}
+
+fn content_range_start(headers: &HeaderMap) -> Result<u64, backoff::Error<anyhow::Error>> {
+    headers
+        .typed_get::<ContentRange>()
+        .and_then(|content_range| content_range.bytes_range())
+        .map(|(start, _)| start)
+        .ok_or_else(|| backoff_permanent(anyhow::format_err!("invalid content-range")))
+}
+
+#[cfg(test)]
+mod tests;
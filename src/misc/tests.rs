@@ -0,0 +1,270 @@
+use super::{Retry, get_segmented, request_with};
+use crate::channel;
+use bytes::Bytes;
+use futures::TryFutureExt;
+use http::{HeaderMap, HeaderValue, Method, Request, Response, StatusCode, header};
+use http_body::Frame;
+use http_body_util::combinators::UnsyncBoxBody;
+use http_body_util::{BodyExt, Empty, Full, StreamBody};
+use hyper::body::Incoming;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::fs;
+use tokio::net::TcpListener;
+
+type Ranges = Arc<Mutex<Vec<Option<String>>>>;
+
+// a stand-in for an origin honoring ranges, which answers with at most `limit` bytes and
+// drops the connection halfway through a whole object
+async fn serve(data: Bytes, limit: usize) -> anyhow::Result<(String, Ranges)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/", listener.local_addr()?);
+    let ranges = Ranges::default();
+    tokio::spawn({
+        let ranges = ranges.clone();
+        async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let data = data.clone();
+                let ranges = ranges.clone();
+                tokio::spawn(hyper::server::conn::http1::Builder::new().serve_connection(
+                    TokioIo::new(stream),
+                    hyper::service::service_fn(move |request| {
+                        let response = handle(&data, limit, &ranges, &request);
+                        async move { response }
+                    }),
+                ));
+            }
+        }
+    });
+    Ok((url, ranges))
+}
+
+fn handle(
+    data: &Bytes,
+    limit: usize,
+    ranges: &Ranges,
+    request: &Request<Incoming>,
+) -> anyhow::Result<Response<UnsyncBoxBody<Bytes, io::Error>>> {
+    let range = request
+        .headers()
+        .get(header::RANGE)
+        .map(|range| range.to_str().map(ToString::to_string))
+        .transpose()?;
+    ranges.lock().unwrap().push(range.clone());
+
+    let Some(range) = range else {
+        let frames = vec![
+            Ok(Frame::data(data.slice(..data.len() / 2))),
+            Err(io::Error::other("connection reset")),
+        ];
+        return Ok(Response::builder()
+            .header(header::CONTENT_LENGTH, data.len())
+            .body(StreamBody::new(futures::stream::iter(frames)).boxed_unsync())?);
+    };
+    let (start, end) = range
+        .strip_prefix("bytes=")
+        .and_then(|range| range.split_once('-'))
+        .ok_or_else(|| anyhow::format_err!("invalid range {range}"))?;
+    let start = start.parse::<usize>()?;
+    let end = if end.is_empty() {
+        data.len()
+    } else {
+        end.parse::<usize>()? + 1
+    };
+    let end = end.min(start + limit);
+    Ok(Response::builder()
+        .status(StatusCode::PARTIAL_CONTENT)
+        .header(
+            header::CONTENT_RANGE,
+            format!("bytes {start}-{}/{}", end - 1, data.len()),
+        )
+        .body(
+            Full::new(data.slice(start..end))
+                .map_err(|e| match e {})
+                .boxed_unsync(),
+        )?)
+}
+
+async fn get(data: &Bytes, limit: usize, segments: u64) -> anyhow::Result<Ranges> {
+    let (url, ranges) = serve(data.clone(), limit).await?;
+    let client = hyper_util::client::legacy::Client::builder(TokioExecutor::new())
+        .build_http::<Empty<Bytes>>();
+    let retry = Retry {
+        max_retries: 16,
+        max_delay: Duration::from_millis(10),
+    };
+
+    let temp_dir = tempfile::tempdir()?;
+    let size = data.len() as u64;
+    let mut channel = channel::new_in(size, temp_dir.path())?;
+    let (writer, reader) = channel.init()?;
+    get_segmented(
+        |range| {
+            let mut builder = Request::get(&url);
+            if let Some(range) = range {
+                builder = builder.header(
+                    header::RANGE,
+                    format!("bytes={}-{}", range.start, range.end - 1),
+                );
+            }
+            let request = builder.body(Empty::new());
+            let client = client.clone();
+            async move {
+                client
+                    .request(request.map_err(super::backoff_permanent)?)
+                    .map_err(super::backoff_transient)
+                    .await
+            }
+        },
+        size,
+        segments,
+        &retry,
+        writer,
+    )
+    .await?;
+    anyhow::ensure!(fs::read(reader.path()).await? == data[..]);
+    Ok(ranges)
+}
+
+fn data() -> Bytes {
+    (0..1 << 20).map(|i: u32| (i % 251) as u8).collect()
+}
+
+#[tokio::test]
+async fn test_get_resume() -> anyhow::Result<()> {
+    let data = data();
+    let ranges = get(&data, data.len(), 1).await?;
+
+    // dropped halfway, then resumed where it stopped
+    let ranges = ranges.lock().unwrap().clone();
+    anyhow::ensure!(
+        ranges
+            == [
+                None,
+                Some(format!("bytes={}-{}", data.len() / 2, data.len() - 1))
+            ],
+        "{ranges:?}"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_get_segments() -> anyhow::Result<()> {
+    let data = data();
+    // every segment arrives in two parts
+    let ranges = get(&data, data.len() / 8, 4).await?;
+
+    let mut ranges = ranges.lock().unwrap().clone();
+    ranges.sort();
+    let quarter = data.len() / 4;
+    let mut expected = (0..4)
+        .flat_map(|i| {
+            let start = i * quarter;
+            let end = start + quarter - 1;
+            [
+                Some(format!("bytes={start}-{end}")),
+                Some(format!("bytes={}-{end}", start + quarter / 2)),
+            ]
+        })
+        .collect::<Vec<_>>();
+    expected.sort();
+    anyhow::ensure!(ranges == expected, "{ranges:?}");
+    Ok(())
+}
+
+type Requests = Arc<Mutex<Vec<(String, Option<HeaderValue>, Bytes)>>>;
+
+// a stand-in for an origin redirecting as `redirects` tells it, keyed by path
+async fn serve_redirects(
+    redirects: Vec<(&'static str, StatusCode, String)>,
+) -> anyhow::Result<(String, Requests)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}", listener.local_addr()?);
+    let requests = Requests::default();
+    tokio::spawn({
+        let requests = requests.clone();
+        async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let redirects = redirects.clone();
+                let requests = requests.clone();
+                tokio::spawn(hyper::server::conn::http1::Builder::new().serve_connection(
+                    TokioIo::new(stream),
+                    hyper::service::service_fn(move |request: Request<Incoming>| {
+                        let redirects = redirects.clone();
+                        let requests = requests.clone();
+                        async move {
+                            let head = format!("{} {}", request.method(), request.uri().path());
+                            let authorization =
+                                request.headers().get(header::AUTHORIZATION).cloned();
+                            let path = request.uri().path().to_string();
+                            let body = request.into_body().collect().await?.to_bytes();
+                            requests.lock().unwrap().push((head, authorization, body));
+                            let mut response = Response::builder();
+                            if let Some((_, status, location)) =
+                                redirects.iter().find(|(from, _, _)| *from == path)
+                            {
+                                response =
+                                    response.status(status).header(header::LOCATION, location);
+                            }
+                            anyhow::Ok(response.body(Full::new(Bytes::new()))?)
+                        }
+                    }),
+                ));
+            }
+        }
+    });
+    Ok((url, requests))
+}
+
+#[tokio::test]
+async fn test_request_redirects() -> anyhow::Result<()> {
+    let (other, other_requests) = serve_redirects(Vec::new()).await?;
+    let (url, requests) = serve_redirects(vec![
+        (
+            "/start",
+            StatusCode::TEMPORARY_REDIRECT,
+            "/same".to_string(),
+        ),
+        ("/same", StatusCode::SEE_OTHER, format!("{other}/other")),
+    ])
+    .await?;
+
+    let authorization = HeaderValue::from_static("Bearer t0k3n");
+    let mut headers = HeaderMap::new();
+    headers.insert(header::AUTHORIZATION, authorization.clone());
+    let response = request_with(
+        &super::client()?,
+        Method::POST,
+        format!("{url}/start").parse()?,
+        headers,
+        || Ok(Full::from("hello world").map_err(Box::from).boxed_unsync()),
+    )
+    .await
+    .map_err(|e| anyhow::format_err!("{e}"))?;
+    anyhow::ensure!(response.status() == StatusCode::OK);
+
+    // a 307 to the same origin keeps the method, body and credentials; a 303 elsewhere
+    // becomes a plain GET without them
+    let body = Bytes::from("hello world");
+    let requests = requests.lock().unwrap().clone();
+    anyhow::ensure!(
+        requests
+            == [
+                (
+                    "POST /start".to_string(),
+                    Some(authorization.clone()),
+                    body.clone()
+                ),
+                ("POST /same".to_string(), Some(authorization), body),
+            ],
+        "{requests:?}"
+    );
+    let other_requests = other_requests.lock().unwrap().clone();
+    anyhow::ensure!(
+        other_requests == [("GET /other".to_string(), None, Bytes::new())],
+        "{other_requests:?}"
+    );
+    Ok(())
+}
@@ -0,0 +1,208 @@
+use crate::{git, misc, timeout};
+use http::{HeaderValue, Uri};
+use hyper_util::client::legacy::connect::proxy::{SocksV5, Tunnel};
+use hyper_util::client::legacy::connect::{Connected, Connection, HttpConnector};
+use hyper_util::client::proxy::matcher::{Intercept, Matcher};
+use hyper_util::rt::TokioIo;
+use std::env;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+use tower::Service;
+use url::Url;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+// connects directly, through an HTTP CONNECT tunnel or through SOCKS5, chosen per destination
+#[derive(Clone, Debug)]
+pub struct Connector {
+    http: HttpConnector,
+    timeouts: timeout::Timeouts,
+    config: &'static git::UrlConfig,
+}
+
+impl Connector {
+    pub fn new(timeouts: timeout::Timeouts) -> Self {
+        Self::with_config(timeouts, misc::config())
+    }
+
+    fn with_config(timeouts: timeout::Timeouts, config: &'static git::UrlConfig) -> Self {
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+        http.set_connect_timeout(timeouts.connect);
+        Self {
+            http,
+            timeouts,
+            config,
+        }
+    }
+}
+
+impl Service<Uri> for Connector {
+    type Response = TokioIo<timeout::Stream<Stream>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.http.poll_ready(cx).map_err(Error::from)
+    }
+
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        let mut http = self.http.clone();
+        let timeouts = self.timeouts;
+        let intercept = intercept(self.config, &dst);
+        Box::pin(async move {
+            let mut proxied = false;
+            let stream = match intercept {
+                None => http.call(dst).await?,
+                Some(intercept) => {
+                    tracing::info!(?dst, proxy = ?intercept);
+                    match intercept.uri().scheme_str() {
+                        Some(scheme @ ("socks5" | "socks5h")) => {
+                            let mut socks = SocksV5::new(intercept.uri().clone(), http)
+                                .local_dns(scheme == "socks5");
+                            if let Some((username, password)) = intercept.raw_auth() {
+                                socks = socks.with_auth(username.to_string(), password.to_string());
+                            }
+                            socks.call(dst).await?
+                        }
+                        // like git and curl, plain http goes to the proxy in absolute-form,
+                        // with Proxy-Authorization added by misc::request
+                        Some("http" | "https") if dst.scheme_str() == Some("http") => {
+                            proxied = true;
+                            http.call(intercept.uri().clone()).await?
+                        }
+                        Some("http" | "https") => {
+                            let mut tunnel = Tunnel::new(intercept.uri().clone(), http);
+                            if let Some(authorization) = intercept.basic_auth() {
+                                tunnel = tunnel.with_auth(authorization.clone());
+                            }
+                            tunnel.call(with_port(dst)?).await?
+                        }
+                        _ => {
+                            return Err(format!("unsupported proxy: {:?}", intercept.uri()).into());
+                        }
+                    }
+                }
+            };
+            Ok(TokioIo::new(timeout::Stream::new(
+                Stream {
+                    inner: stream.into_inner(),
+                    proxied,
+                },
+                timeouts,
+            )))
+        })
+    }
+}
+
+// for plain http requests sent to an HTTP proxy
+pub fn authorization(url: &Url) -> Option<HeaderValue> {
+    authorization_with(misc::config(), url)
+}
+
+fn authorization_with(config: &git::UrlConfig, url: &Url) -> Option<HeaderValue> {
+    if url.scheme() != "http" {
+        return None;
+    }
+    let intercept = intercept(config, &url.as_str().parse().ok()?)?;
+    match intercept.uri().scheme_str() {
+        Some("http" | "https") => intercept.basic_auth().cloned(),
+        _ => None,
+    }
+}
+
+// lfs.<url>.proxy, then http.<url>.proxy and http.proxy, then HTTPS_PROXY and friends;
+// NO_PROXY applies to all of them, and an empty value means no proxy. Connectors only see
+// scheme://host:port, so <url> matches per host and entries with a path never apply
+fn intercept(config: &git::UrlConfig, dst: &Uri) -> Option<Intercept> {
+    let url = dst.to_string().parse::<Url>().ok()?;
+    let proxy = config
+        .get("lfs", "proxy", &url)
+        .or_else(|| config.get("http", "proxy", &url));
+    let matcher = match proxy {
+        Some(proxy) => Matcher::builder()
+            .all(proxy)
+            .no(env::var("NO_PROXY")
+                .or_else(|_| env::var("no_proxy"))
+                .unwrap_or_default())
+            .build(),
+        None => Matcher::from_env(),
+    };
+    matcher.intercept(dst)
+}
+
+// a connection to the proxy itself tells hyper to send requests in absolute-form
+#[derive(Debug)]
+pub struct Stream {
+    inner: TcpStream,
+    proxied: bool,
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+impl Connection for Stream {
+    fn connected(&self) -> Connected {
+        self.inner.connected().proxy(self.proxied)
+    }
+}
+
+fn with_port(dst: Uri) -> Result<Uri, Error> {
+    if dst.port().is_some() {
+        return Ok(dst);
+    }
+    let port = if dst.scheme_str() == Some("http") {
+        80
+    } else {
+        443
+    };
+    let host = dst.host().ok_or("missing host")?;
+    Ok(Uri::builder()
+        .scheme(dst.scheme_str().unwrap_or("https"))
+        .authority(format!("{host}:{port}"))
+        .path_and_query("/")
+        .build()?)
+}
+
+#[cfg(test)]
+mod tests;
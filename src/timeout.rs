@@ -0,0 +1,178 @@
+use crate::misc;
+use hyper_util::client::legacy::connect::{Connected, Connection};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{Instant, Sleep};
+
+// seconds, as git-lfs
+const DEFAULT: u64 = 30;
+
+// milliseconds, 0 disables; defaults from lfs.dialtimeout, lfs.tlstimeout and lfs.activitytimeout
+#[derive(Clone, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct Args {
+    connect: Option<u64>,
+    // the TLS (and proxy) handshake after connecting
+    tls: Option<u64>,
+    // from the last byte sent to the first byte received
+    first_byte: Option<u64>,
+    // between two reads otherwise
+    idle: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Timeouts {
+    pub connect: Option<Duration>,
+    pub tls: Option<Duration>,
+    pub first_byte: Option<Duration>,
+    pub idle: Option<Duration>,
+}
+
+impl Args {
+    pub fn timeouts(&self) -> Timeouts {
+        let config = |name| {
+            misc::config()
+                .get_global("lfs", name)
+                .and_then(|value| value.parse::<u64>().ok())
+                .map_or(DEFAULT, |value| value)
+                * 1000
+        };
+        let duration = |value: Option<u64>, name| {
+            Some(value.unwrap_or_else(|| config(name)))
+                .filter(|value| *value > 0)
+                .map(Duration::from_millis)
+        };
+        Timeouts {
+            connect: duration(self.connect, "dialtimeout"),
+            tls: duration(self.tls, "tlstimeout"),
+            first_byte: duration(self.first_byte, "activitytimeout"),
+            idle: duration(self.idle, "activitytimeout"),
+        }
+    }
+}
+
+// fails a read that waits longer than the first-byte or idle timeout
+#[derive(Debug)]
+pub struct Stream<T> {
+    inner: T,
+    timeouts: Timeouts,
+    // a write since the last read, so the peer owes a response
+    awaiting: bool,
+    last: Instant,
+    sleep: Pin<Box<Sleep>>,
+}
+
+impl<T> Stream<T> {
+    pub fn new(inner: T, timeouts: Timeouts) -> Self {
+        let last = Instant::now();
+        Self {
+            inner,
+            timeouts,
+            awaiting: false,
+            last,
+            sleep: Box::pin(tokio::time::sleep_until(last)),
+        }
+    }
+}
+
+impl<T> AsyncRead for Stream<T>
+where
+    T: AsyncRead + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        if let Poll::Ready(result) = Pin::new(&mut this.inner).poll_read(cx, buf) {
+            this.awaiting = false;
+            this.last = Instant::now();
+            return Poll::Ready(result);
+        }
+        let timeout = if this.awaiting {
+            this.timeouts.first_byte
+        } else {
+            this.timeouts.idle
+        };
+        if let Some(timeout) = timeout {
+            let deadline = this.last + timeout;
+            if this.sleep.deadline() != deadline {
+                this.sleep.as_mut().reset(deadline);
+            }
+            if this.sleep.as_mut().poll(cx).is_ready() {
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    if this.awaiting {
+                        "timed out waiting for a response"
+                    } else {
+                        "timed out reading"
+                    },
+                )));
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl<T> AsyncWrite for Stream<T>
+where
+    T: AsyncWrite + Unpin,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = poll
+            && n > 0
+        {
+            self.awaiting = true;
+            self.last = Instant::now();
+        }
+        poll
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write_vectored(cx, bufs);
+        if let Poll::Ready(Ok(n)) = poll
+            && n > 0
+        {
+            self.awaiting = true;
+            self.last = Instant::now();
+        }
+        poll
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+impl<T> Connection for Stream<T>
+where
+    T: Connection,
+{
+    fn connected(&self) -> Connected {
+        self.inner.connected()
+    }
+}
+
+#[cfg(test)]
+mod tests;
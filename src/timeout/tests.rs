@@ -0,0 +1,32 @@
+use super::{Stream, Timeouts};
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+#[tokio::test]
+async fn test() -> anyhow::Result<()> {
+    let (client, mut server) = tokio::io::duplex(64);
+    let mut client = Stream::new(
+        client,
+        Timeouts {
+            connect: None,
+            tls: None,
+            first_byte: Some(Duration::from_millis(100)),
+            idle: Some(Duration::from_millis(100)),
+        },
+    );
+    let mut buf = [0; 5];
+
+    client.write_all(b"hello").await?;
+    server.read_exact(&mut buf).await?;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    server.write_all(b"world").await?;
+    client.read_exact(&mut buf).await?;
+    anyhow::ensure!(&buf == b"world");
+
+    // the server went quiet
+    let e = client.read(&mut buf).await.unwrap_err();
+    anyhow::ensure!(e.kind() == io::ErrorKind::TimedOut);
+
+    Ok(())
+}
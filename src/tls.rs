@@ -0,0 +1,246 @@
+use crate::{git, misc, proxy, timeout};
+use http::Uri;
+use hyper_rustls::{ConfigBuilderExt, HttpsConnector, MaybeHttpsStream};
+use hyper_util::rt::TokioIo;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tower::Service;
+use url::Url;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+// http.sslCAInfo, http.sslCAPath, http.sslCert, http.sslKey and http.sslVerify
+#[derive(Clone, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct Args {
+    // PEM bundle, replaces the system roots
+    ca_info: Option<PathBuf>,
+    // directory of PEM certificates, replaces the system roots
+    ca_path: Option<PathBuf>,
+    // PEM client certificate chain, for mTLS
+    cert: Option<PathBuf>,
+    // PEM private key, defaults to the one in `cert`
+    key: Option<PathBuf>,
+    verify: Option<bool>,
+}
+
+// picks the TLS settings per destination, from git config overridden by `args`; connectors only
+// see scheme://host:port, so http.<url>.ssl* entries match per host and those with a path never apply
+#[derive(Clone, Debug)]
+pub struct Connector {
+    proxy: proxy::Connector,
+    timeouts: timeout::Timeouts,
+    args: Option<Args>,
+    connectors: Arc<Mutex<HashMap<Args, HttpsConnector<proxy::Connector>>>>,
+}
+
+impl Connector {
+    pub fn new(args: Option<Args>, timeout: Option<timeout::Args>) -> Self {
+        let timeouts = timeout.unwrap_or_default().timeouts();
+        Self {
+            proxy: proxy::Connector::new(timeouts),
+            timeouts,
+            args,
+            connectors: Arc::default(),
+        }
+    }
+
+    fn connector(&self, dst: &Uri) -> anyhow::Result<HttpsConnector<proxy::Connector>> {
+        let mut args = dst
+            .to_string()
+            .parse::<Url>()
+            .map(|url| Args::config(&url))
+            .unwrap_or_default();
+        if let Some(overrides) = &self.args {
+            args = overrides.clone().or(args);
+        }
+
+        let mut connectors = self
+            .connectors
+            .lock()
+            .map_err(|_| anyhow::format_err!("poisoned"))?;
+        if let Some(connector) = connectors.get(&args) {
+            return Ok(connector.clone());
+        }
+        tracing::info!(?dst, tls = ?args);
+        let connector = hyper_rustls::HttpsConnectorBuilder::new()
+            .with_tls_config(args.client_config()?)
+            .https_or_http()
+            .enable_http1()
+            .enable_http2()
+            .wrap_connector(self.proxy.clone());
+        connectors.insert(args, connector.clone());
+        Ok(connector)
+    }
+}
+
+impl Service<Uri> for Connector {
+    type Response = MaybeHttpsStream<TokioIo<timeout::Stream<proxy::Stream>>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        let connecting = match self.connector(&dst) {
+            Ok(mut connector) => connector.call(dst),
+            Err(e) => return Box::pin(async move { Err(e.into()) }),
+        };
+        // connect, then any proxy and TLS handshakes
+        match self
+            .timeouts
+            .connect
+            .zip(self.timeouts.tls)
+            .map(|(connect, tls)| connect + tls)
+        {
+            Some(timeout) => Box::pin(async move {
+                tokio::time::timeout(timeout, connecting)
+                    .await
+                    .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "timed out connecting"))?
+            }),
+            None => connecting,
+        }
+    }
+}
+
+impl Args {
+    fn config(url: &Url) -> Self {
+        Self::config_with(misc::config(), url)
+    }
+
+    fn config_with(config: &git::UrlConfig, url: &Url) -> Self {
+        let path = |name| config.get("http", name, url).map(expand_home);
+        Self {
+            ca_info: path("sslcainfo"),
+            ca_path: path("sslcapath"),
+            cert: path("sslcert"),
+            key: path("sslkey"),
+            // git booleans, where an empty value is false
+            verify: config.get("http", "sslverify", url).map(|value| {
+                !matches!(
+                    value.to_lowercase().as_str(),
+                    "" | "false" | "no" | "off" | "0"
+                )
+            }),
+        }
+    }
+
+    fn or(self, other: Self) -> Self {
+        Self {
+            ca_info: self.ca_info.or(other.ca_info),
+            ca_path: self.ca_path.or(other.ca_path),
+            cert: self.cert.or(other.cert),
+            key: self.key.or(other.key),
+            verify: self.verify.or(other.verify),
+        }
+    }
+
+    fn client_config(&self) -> anyhow::Result<ClientConfig> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?;
+        let builder = if self.verify == Some(false) {
+            builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(NoVerifier(provider)))
+        } else if self.ca_info.is_some() || self.ca_path.is_some() {
+            let mut roots = RootCertStore::empty();
+            if let Some(path) = &self.ca_info {
+                for cert in CertificateDer::pem_file_iter(path)? {
+                    roots.add(cert?)?;
+                }
+            }
+            if let Some(path) = &self.ca_path {
+                for entry in fs::read_dir(path)? {
+                    // anything that is not a PEM certificate is skipped
+                    if let Ok(certs) = CertificateDer::pem_file_iter(entry?.path()) {
+                        roots.add_parsable_certificates(certs.flatten());
+                    }
+                }
+            }
+            builder.with_root_certificates(roots)
+        } else {
+            builder.with_native_roots()?
+        };
+        let config = if let Some(cert) = &self.cert {
+            let certs = CertificateDer::pem_file_iter(cert)?.collect::<Result<Vec<_>, _>>()?;
+            let key = PrivateKeyDer::from_pem_file(self.key.as_ref().unwrap_or(cert))?;
+            builder.with_client_auth_cert(certs, key)?
+        } else {
+            builder.with_no_client_auth()
+        };
+        Ok(config)
+    }
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(path), Some(home)) => PathBuf::from(home).join(path),
+        _ => PathBuf::from(path),
+    }
+}
+
+// http.sslVerify=false: any certificate, but the handshake signatures are still checked
+#[derive(Debug)]
+struct NoVerifier(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for NoVerifier {
+    fn verify_server_cert(
+        &self,
+        _: &CertificateDer<'_>,
+        _: &[CertificateDer<'_>],
+        _: &ServerName<'_>,
+        _: &[u8],
+        _: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests;
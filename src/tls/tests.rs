@@ -0,0 +1,218 @@
+use super::Args;
+use crate::git;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{ClientConfig, ClientConnection, RootCertStore, ServerConfig, ServerConnection};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::{env, fs, thread};
+use tokio::process::Command;
+
+// a CA, and a server and a client certificate it signed
+async fn certificates(dir: &Path) -> anyhow::Result<()> {
+    crate::misc::spawn(
+        Command::new("sh").current_dir(dir).arg("-c").arg(concat!(
+            "set -e\n",
+            "req='openssl req -x509 -newkey ec -pkeyopt ec_paramgen_curve:P-256 -nodes -days 1'\n",
+            "$req -keyout ca.key -out ca.pem -subj /CN=ca\n",
+            "for name in server client; do\n",
+            "  $req -keyout $name.key -out $name.pem -subj /CN=localhost -CA ca.pem -CAkey ca.key \\\n",
+            "    -addext subjectAltName=DNS:localhost -addext basicConstraints=critical,CA:FALSE\n",
+            "done\n",
+        )),
+        None,
+    )
+    .await?;
+    Ok(())
+}
+
+// a TLS server on a thread of its own, optionally requiring a client certificate signed by the CA,
+// which echoes one message
+fn serve(
+    dir: &Path,
+    mtls: bool,
+) -> anyhow::Result<(String, thread::JoinHandle<anyhow::Result<()>>)> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+    let builder = if mtls {
+        let mut roots = RootCertStore::empty();
+        roots.add(CertificateDer::from_pem_file(dir.join("ca.pem"))?)?;
+        builder.with_client_cert_verifier(
+            WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider).build()?,
+        )
+    } else {
+        builder.with_no_client_auth()
+    };
+    let config = Arc::new(builder.with_single_cert(
+        CertificateDer::pem_file_iter(dir.join("server.pem"))?.collect::<Result<_, _>>()?,
+        PrivateKeyDer::from_pem_file(dir.join("server.key"))?,
+    )?);
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let address = listener.local_addr()?.to_string();
+    let handle = thread::spawn(move || {
+        let (stream, _) = listener.accept()?;
+        let mut stream = rustls::StreamOwned::new(ServerConnection::new(config)?, stream);
+        let mut buf = [0; 5];
+        stream.read_exact(&mut buf)?;
+        stream.write_all(&buf)?;
+        stream.flush()?;
+        Ok(())
+    });
+    Ok((address, handle))
+}
+
+fn handshake(address: &str, config: ClientConfig) -> anyhow::Result<()> {
+    let connection = ClientConnection::new(Arc::new(config), ServerName::try_from("localhost")?)?;
+    let mut stream = rustls::StreamOwned::new(connection, TcpStream::connect(address)?);
+    stream.write_all(b"hello")?;
+    let mut buf = [0; 5];
+    stream.read_exact(&mut buf)?;
+    anyhow::ensure!(&buf == b"hello");
+    Ok(())
+}
+
+fn args(value: serde_json::Value) -> anyhow::Result<Args> {
+    Ok(serde_json::from_value(value)?)
+}
+
+#[test]
+fn test_config() -> anyhow::Result<()> {
+    let config = git::parse_url_config(concat!(
+        "http.sslcainfo\n~/ca.pem\0",
+        "http.sslcert\n/etc/client.pem\0",
+        "http.https://insecure.example.com.sslverify\n\0",
+        "http.https://secure.example.com.sslverify\0",
+        "http.https://off.example.com.sslverify\nOff\0",
+    ));
+    let home = PathBuf::from(env::var_os("HOME").unwrap_or_default());
+    let url = "https://example.com/".parse()?;
+    let args = Args::config_with(&config, &url);
+    anyhow::ensure!(args.ca_info == Some(home.join("ca.pem")), "{args:?}");
+    anyhow::ensure!(
+        args.cert == Some(PathBuf::from("/etc/client.pem")),
+        "{args:?}"
+    );
+    anyhow::ensure!(args.key.is_none() && args.verify.is_none(), "{args:?}");
+
+    // an empty value is false, and a missing one true
+    for (url, verify) in [
+        ("https://insecure.example.com/", false),
+        ("https://secure.example.com/", true),
+        ("https://off.example.com/", false),
+    ] {
+        let args = Args::config_with(&config, &url.parse()?);
+        anyhow::ensure!(args.verify == Some(verify), "{url}: {args:?}");
+    }
+
+    // the cache's own settings win, field by field
+    let args =
+        self::args(serde_json::json!({"ca_info": "/etc/ca.pem", "key": "/etc/client.key"}))?.or(
+            Args::config_with(&config, &"https://insecure.example.com/".parse()?),
+        );
+    anyhow::ensure!(
+        args == self::args(serde_json::json!({
+            "ca_info": "/etc/ca.pem",
+            "cert": "/etc/client.pem",
+            "key": "/etc/client.key",
+            "verify": false,
+        }))?,
+        "{args:?}"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_config() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let dir = temp_dir.path();
+    certificates(dir).await?;
+
+    // the CA bundle replaces the system roots
+    let (address, server) = serve(dir, false)?;
+    handshake(
+        &address,
+        args(serde_json::json!({"ca_info": dir.join("ca.pem")}))?.client_config()?,
+    )?;
+    server
+        .join()
+        .map_err(|_| anyhow::format_err!("panicked"))??;
+
+    let ca_path = dir.join("certs");
+    fs::create_dir(&ca_path)?;
+    fs::copy(dir.join("ca.pem"), ca_path.join("ca.pem"))?;
+    fs::write(ca_path.join("README"), "not a certificate")?;
+    let (address, server) = serve(dir, false)?;
+    handshake(
+        &address,
+        args(serde_json::json!({"ca_path": ca_path}))?.client_config()?,
+    )?;
+    server
+        .join()
+        .map_err(|_| anyhow::format_err!("panicked"))??;
+
+    // an unknown CA is refused, unless verification is off
+    let (address, _server) = serve(dir, false)?;
+    anyhow::ensure!(
+        handshake(
+            &address,
+            args(serde_json::json!({"ca_info": dir.join("client.pem")}))?.client_config()?
+        )
+        .is_err()
+    );
+    let (address, server) = serve(dir, false)?;
+    handshake(
+        &address,
+        args(serde_json::json!({"verify": false}))?.client_config()?,
+    )?;
+    server
+        .join()
+        .map_err(|_| anyhow::format_err!("panicked"))??;
+
+    // mTLS, with the key in its own file or next to the certificate
+    let (address, _server) = serve(dir, true)?;
+    anyhow::ensure!(
+        handshake(
+            &address,
+            args(serde_json::json!({"ca_info": dir.join("ca.pem")}))?.client_config()?
+        )
+        .is_err()
+    );
+    let (address, server) = serve(dir, true)?;
+    handshake(
+        &address,
+        args(serde_json::json!({
+            "ca_info": dir.join("ca.pem"),
+            "cert": dir.join("client.pem"),
+            "key": dir.join("client.key"),
+        }))?
+        .client_config()?,
+    )?;
+    server
+        .join()
+        .map_err(|_| anyhow::format_err!("panicked"))??;
+
+    let bundle = dir.join("bundle.pem");
+    fs::write(
+        &bundle,
+        [
+            fs::read(dir.join("client.pem"))?,
+            fs::read(dir.join("client.key"))?,
+        ]
+        .concat(),
+    )?;
+    let (address, server) = serve(dir, true)?;
+    handshake(
+        &address,
+        args(serde_json::json!({"ca_info": dir.join("ca.pem"), "cert": bundle}))?
+            .client_config()?,
+    )?;
+    server
+        .join()
+        .map_err(|_| anyhow::format_err!("panicked"))??;
+    Ok(())
+}
@@ -1,25 +1,123 @@
 use crate::{cache, channel, git, git_lfs, jsonl, logs, misc};
-use chrono::Utc;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
 use clap::Parser;
 use futures::TryStreamExt;
-use http::{Request, StatusCode};
-use http_body_util::{BodyExt, Empty};
+use futures::future::Either;
+use headers::HeaderMapExt;
+use http::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::fmt::Debug;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::pin;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::fs::{self, File};
 use tokio::io;
+use tokio::sync::Mutex;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
+use url::Url;
 
 #[derive(Debug, Parser)]
 pub struct Args {
     #[clap(long)]
     cache: Option<cache::Args>,
+    #[clap(long)]
+    hedge: Option<Hedge>,
+    #[clap(long)]
+    circuit_breaker: Option<CircuitBreaker>,
+    #[clap(long)]
+    negative_cache: Option<NegativeCache>,
+    // concurrent ranges for large origin downloads
+    #[clap(long)]
+    segments: Option<u64>,
+}
+
+// start the origin download alongside a slow cache read
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Hedge {
+    // milliseconds, how often the cache read is checked
+    timeout: u64,
+    // bytes per second; without it, only a cache that has sent nothing is hedged
+    min_throughput: Option<u64>,
+}
+
+impl Default for Hedge {
+    fn default() -> Self {
+        Self {
+            timeout: 3_000,
+            min_throughput: None,
+        }
+    }
+}
+
+impl FromStr for Hedge {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_str(s).map_err(|e| e.to_string())
+    }
+}
+
+// bypass a failing cache instead of waiting for it on every object
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CircuitBreaker {
+    // consecutive failures (misses do not count)
+    failures: usize,
+    // milliseconds
+    cool_down: u64,
+    // milliseconds, a slower cache read counts as a failure
+    timeout: Option<u64>,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self {
+            failures: 5,
+            cool_down: 60_000,
+            timeout: None,
+        }
+    }
+}
+
+impl FromStr for CircuitBreaker {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_str(s).map_err(|e| e.to_string())
+    }
+}
+
+// objects the origin reported as missing, kept locally and never in the shared cache
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct NegativeCache {
+    // milliseconds
+    ttl: u64,
+    // keep entries across invocations, under the git dir
+    persist: bool,
+}
+
+impl Default for NegativeCache {
+    fn default() -> Self {
+        Self {
+            ttl: 300_000,
+            persist: false,
+        }
+    }
+}
+
+impl FromStr for NegativeCache {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_str(s).map_err(|e| e.to_string())
+    }
 }
 
 pub async fn main(args: Args) -> anyhow::Result<()> {
@@ -44,6 +142,7 @@ pub async fn main(args: Args) -> anyhow::Result<()> {
         .with(tracing_subscriber::filter::EnvFilter::from_default_env())
         .try_init()?;
 
+    misc::init(git::url_config(&current_dir).await?);
     let mut context = Context::new(args, current_dir, git_dir, logs_dir).await?;
 
     let mut stdin = jsonl::Reader::new(io::stdin());
@@ -107,7 +206,36 @@ struct Context {
     cache: Option<cache::Cache>,
     operation: Option<git_lfs::Operation>,
     remote: Option<String>,
-    server_discovery: Option<Arc<git_lfs::server_discovery::Response>>,
+    server_discovery: Mutex<Option<Arc<git_lfs::server_discovery::Response>>>,
+    credentials: git::Credentials,
+    // endpoints whose lfs.<url>.access has been set to basic in this session
+    access_recorded: Mutex<HashSet<Url>>,
+    hedge: Option<Hedge>,
+    health: Mutex<Health>,
+    negative: Mutex<Negative>,
+    segments: u64,
+    retry: misc::Retry,
+}
+
+#[derive(Debug)]
+struct Health {
+    // off unless --circuit-breaker is given
+    circuit_breaker: Option<CircuitBreaker>,
+    failures: usize,
+    bypass_until: Option<Instant>,
+}
+
+#[derive(Debug)]
+struct Negative {
+    ttl: chrono::Duration,
+    path: Option<PathBuf>,
+    entries: HashMap<String, NegativeEntry>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct NegativeEntry {
+    error: git_lfs::Error,
+    expires: DateTime<Utc>,
 }
 
 impl Context {
@@ -129,16 +257,27 @@ impl Context {
         } else {
             None
         };
-
+        let negative = Negative::load(args.negative_cache.unwrap_or_default(), &git_dir).await?;
         Ok(Self {
             client: misc::client()?,
+            credentials: git::Credentials::new(current_dir.clone()),
+            access_recorded: Mutex::default(),
             current_dir,
             git_dir,
             logs: jsonl::Writer::new(File::from_std(logs)),
             cache,
             operation: None,
             remote: None,
-            server_discovery: None,
+            server_discovery: Mutex::new(None),
+            hedge: args.hedge,
+            health: Mutex::new(Health {
+                circuit_breaker: args.circuit_breaker,
+                failures: 0,
+                bypass_until: None,
+            }),
+            negative: Mutex::new(negative),
+            segments: args.segments.unwrap_or(misc::SEGMENTS),
+            retry: misc::Retry::config()?,
         })
     }
 
@@ -150,10 +289,11 @@ impl Context {
     }
 
     async fn server_discovery(
-        &mut self,
+        &self,
         authorization: bool,
     ) -> anyhow::Result<Arc<git_lfs::server_discovery::Response>> {
-        let response = match (self.server_discovery.clone(), authorization) {
+        let mut server_discovery = self.server_discovery.lock().await;
+        let response = match (server_discovery.clone(), authorization) {
             (None, _) | (_, true) => {
                 let operation = self
                     .operation
@@ -162,16 +302,88 @@ impl Context {
                     .remote
                     .as_ref()
                     .ok_or_else(|| anyhow::format_err!("uninitialized"))?;
-                let response =
-                    git_lfs::server_discovery(&self.current_dir, operation, remote, authorization)
-                        .await?;
-                self.server_discovery.insert(Arc::new(response)).clone()
+                let mut response = git_lfs::server_discovery(
+                    &self.current_dir,
+                    operation,
+                    remote,
+                    authorization.then_some(&self.credentials),
+                )
+                .await?;
+                // skips the unauthenticated round trip when basic authentication worked before
+                if !authorization
+                    && git_lfs::server_discovery::access(&response.href)
+                        == git_lfs::server_discovery::Access::Basic
+                {
+                    response = git_lfs::server_discovery(
+                        &self.current_dir,
+                        operation,
+                        remote,
+                        Some(&self.credentials),
+                    )
+                    .await?;
+                }
+                server_discovery.insert(Arc::new(response)).clone()
             }
             (Some(response), _) => response,
         };
         Ok(response)
     }
 
+    // approves or rejects the credential, and remembers that basic authentication works;
+    // refused git-lfs-authenticate results are not reused. Failures here are only logged,
+    // so that a credential helper cannot fail a transfer or hide the server's error
+    async fn authenticated<T>(
+        &self,
+        server_discovery: &git_lfs::server_discovery::Response,
+        response: &anyhow::Result<T>,
+    ) {
+        if let Some(authenticated) = &server_discovery.authenticated
+            && let Err(e) = response
+            && e.downcast_ref::<git_lfs::Error>()
+                .is_some_and(|e| e.code == StatusCode::UNAUTHORIZED)
+            && let Err(error) =
+                git_lfs::server_discovery::forget(&self.current_dir, authenticated).await
+        {
+            tracing::warn!(?error, "git-lfs-authenticate result not forgotten");
+        }
+        let Some(url) = &server_discovery.credential else {
+            return;
+        };
+        match response {
+            Ok(_) => {
+                if let Err(error) = self.credentials.approve(url).await {
+                    tracing::warn!(?error, "credential not approved");
+                }
+                if git_lfs::server_discovery::access(&server_discovery.href)
+                    != git_lfs::server_discovery::Access::Basic
+                    && self
+                        .access_recorded
+                        .lock()
+                        .await
+                        .insert(server_discovery.href.clone())
+                    // concurrent transfer agents contend for .git/config.lock
+                    && let Err(error) = git_lfs::server_discovery::set_access(
+                        &self.current_dir,
+                        &server_discovery.href,
+                        git_lfs::server_discovery::Access::Basic,
+                    )
+                    .await
+                {
+                    tracing::warn!(?error, "access not recorded");
+                }
+            }
+            Err(e)
+                if e.downcast_ref::<git_lfs::Error>()
+                    .is_some_and(|e| e.code == StatusCode::UNAUTHORIZED) =>
+            {
+                if let Err(error) = self.credentials.reject(url).await {
+                    tracing::warn!(?error, "credential not rejected");
+                }
+            }
+            Err(_) => {}
+        }
+    }
+
     #[tracing::instrument(err, ret, skip(stdout))]
     async fn download(
         &mut self,
@@ -184,180 +396,413 @@ impl Context {
         let temp_dir = self.git_dir.join("lfs").join("tmp");
         fs::create_dir_all(&temp_dir).await?;
 
-        let path = if let Some(cache) = &self.cache {
-            let mut channel = channel::new_in(size, &temp_dir)?;
-            let (writer, reader) = channel.init()?;
-            if let Ok((source, _, _)) = futures::future::try_join3(
-                cache.get(oid, size, writer),
-                async {
-                    let mut hasher = Sha256::new();
-                    let mut body = pin::pin!(reader.stream()?);
-                    while let Some(data) = body.try_next().await? {
-                        hasher.update(data);
-                    }
-                    anyhow::ensure!(oid == hex::encode(hasher.finalize()));
-                    Ok(())
-                },
-                progress(oid, &reader, &mut *stdout),
-            )
-            .await
-            {
-                let path = channel.keep()?;
-                self.logs
-                    .write(&logs::Line {
-                        operation: git_lfs::Operation::Download,
-                        oid: Cow::Borrowed(oid),
-                        size,
-                        cache: Some(source),
-                        start,
-                        finish: Utc::now(),
-                    })
-                    .await?;
-                Some(path)
-            } else {
-                None
-            }
+        let state = Mutex::new(Progress {
+            oid,
+            stdout,
+            bytes_so_far: 0,
+            bytes_reported: 0,
+        });
+        let mut cache_channel = channel::new_in(size, &temp_dir)?;
+        let mut origin_channel = channel::new_in(size, &temp_dir)?;
+        let cache = if self.health.lock().await.available() {
+            self.cache.as_ref()
         } else {
             None
         };
-
-        if let Some(path) = path {
-            Ok(path)
+        let source = {
+            let get_cache =
+                cache.map(|cache| self.get_cache(cache, oid, size, &mut cache_channel, &state));
+            // a hedged cache is not written to, so it cannot hold the origin up; objects the
+            // origin wins are cached by uploads and unhedged downloads
+            let put = cache.filter(|_| self.hedge.is_none());
+            let origin = self.get_origin(put, oid, size, &mut origin_channel, &state);
+            race(self.hedge.as_ref(), get_cache, origin, &state).await?
+        };
+        let path = if source.is_some() {
+            cache_channel.keep()?
         } else {
-            let request = git_lfs::batch::Request {
+            origin_channel.keep()?
+        };
+        self.logs
+            .write(&logs::Line {
                 operation: git_lfs::Operation::Download,
-                transfers: &[git_lfs::batch::request::Transfer::Basic],
-                objects: &[git_lfs::batch::request::Object { oid, size }],
-            };
-            let server_discovery = self.server_discovery(false).await?;
-            let response = git_lfs::batch(
-                &self.client,
-                &server_discovery.href,
-                &server_discovery.header,
-                &request,
-            )
-            .await;
-            let response = match response {
-                Ok(response) => Ok(response),
-                Err(e) => match e.downcast::<git_lfs::Error>() {
-                    Ok(e) if e.code == StatusCode::UNAUTHORIZED => {
-                        let server_discovery = self.server_discovery(true).await?;
-                        git_lfs::batch(
-                            &self.client,
-                            &server_discovery.href,
-                            &server_discovery.header,
-                            &request,
-                        )
-                        .await
-                    }
-                    Ok(e) => Err(e.into()),
-                    Err(e) => Err(e),
-                },
-            }?;
-
-            let object = response
-                .objects
-                .into_iter()
-                .find(|object| object.oid == oid)
-                .ok_or_else(|| anyhow::format_err!("missing object"))?;
-            match object.inner {
-                git_lfs::batch::response::Inner::Actions {
-                    download: Some(download),
-                    ..
-                } => {
-                    let builder = Request::get(download.href.as_ref());
-                    let builder = download
-                        .header
-                        .iter()
-                        .fold(builder, |builder, (name, value)| {
-                            builder.header(name, value)
-                        });
-                    let request = builder.body(Empty::new().map_err(Box::from).boxed_unsync())?;
-                    let response = self.client.request(request).await?;
-                    let (parts, mut body) = response.into_parts();
-                    if parts.status.is_success() {
-                        let mut channel = channel::new_in(size, &temp_dir)?;
-                        let (mut writer, reader) = channel.init()?;
-                        futures::future::try_join3(
-                            async {
-                                while let Some(frame) = body.frame().await.transpose()? {
-                                    if let Ok(data) = frame.into_data() {
-                                        writer.write(&data).await?;
-                                    }
-                                }
-                                Ok(writer.finish().await?)
-                            },
-                            async {
-                                if let Some(cache) = &self.cache {
-                                    cache.put(oid, size, &reader).await?;
+                oid: Cow::Borrowed(oid),
+                size,
+                cache: source,
+                start,
+                finish: Utc::now(),
+            })
+            .await?;
+        Ok(path)
+    }
+
+    async fn get_cache(
+        &self,
+        cache: &cache::Cache,
+        oid: &str,
+        size: u64,
+        channel: &mut channel::Channel,
+        state: &Mutex<Progress<'_>>,
+    ) -> anyhow::Result<cache::Source> {
+        let (writer, reader) = channel.init()?;
+        let timeout = self
+            .health
+            .lock()
+            .await
+            .circuit_breaker
+            .as_ref()
+            .and_then(|circuit_breaker| circuit_breaker.timeout);
+        let (source, _, _) = futures::future::try_join3(
+            async {
+                let result = if let Some(timeout) = timeout {
+                    tokio::time::timeout(
+                        Duration::from_millis(timeout),
+                        cache.get(oid, size, writer),
+                    )
+                    .await
+                    .unwrap_or_else(|e| Err(e.into()))
+                } else {
+                    cache.get(oid, size, writer).await
+                };
+                self.health.lock().await.record(&result);
+                result
+            },
+            async {
+                let mut hasher = Sha256::new();
+                let mut body = pin::pin!(reader.stream()?);
+                while let Some(data) = body.try_next().await? {
+                    hasher.update(data);
+                }
+                anyhow::ensure!(oid == hex::encode(hasher.finalize()));
+                Ok(())
+            },
+            progress(&reader, state),
+        )
+        .await?;
+        Ok(source)
+    }
+
+    async fn get_origin(
+        &self,
+        cache: Option<&cache::Cache>,
+        oid: &str,
+        size: u64,
+        channel: &mut channel::Channel,
+        state: &Mutex<Progress<'_>>,
+    ) -> anyhow::Result<()> {
+        if let Some(e) = self.negative.lock().await.get(oid) {
+            tracing::info!("known missing on origin");
+            return Err(e.into());
+        }
+
+        let request = git_lfs::batch::Request {
+            operation: git_lfs::Operation::Download,
+            transfers: &[git_lfs::batch::request::Transfer::Basic],
+            objects: &[git_lfs::batch::request::Object { oid, size }],
+        };
+        let server_discovery = self.server_discovery(false).await?;
+        let response = git_lfs::batch(
+            &self.client,
+            &self.retry,
+            &server_discovery.href,
+            &server_discovery.header,
+            &request,
+        )
+        .await;
+        self.authenticated(&server_discovery, &response).await;
+        let response = match response {
+            Ok(response) => Ok(response),
+            Err(e) => match e.downcast::<git_lfs::Error>() {
+                Ok(e) if e.code == StatusCode::UNAUTHORIZED => {
+                    let server_discovery = self.server_discovery(true).await?;
+                    let response = git_lfs::batch(
+                        &self.client,
+                        &self.retry,
+                        &server_discovery.href,
+                        &server_discovery.header,
+                        &request,
+                    )
+                    .await;
+                    self.authenticated(&server_discovery, &response).await;
+                    response
+                }
+                Ok(e) => Err(e.into()),
+                Err(e) => Err(e),
+            },
+        }?;
+
+        let object = response
+            .objects
+            .into_iter()
+            .find(|object| object.oid == oid)
+            .ok_or_else(|| anyhow::format_err!("missing object"))?;
+        match object.inner {
+            git_lfs::batch::response::Inner::Actions {
+                download: Some(download),
+                ..
+            } => {
+                let (writer, reader) = channel.init()?;
+                futures::future::try_join3(
+                    misc::get(
+                        |range| {
+                            let download = &download;
+                            async move {
+                                let mut headers = download.header.clone();
+                                if let Some(range) = range {
+                                    headers.typed_insert(
+                                        headers::Range::bytes(range)
+                                            .map_err(misc::backoff_permanent)?,
+                                    );
                                 }
-                                Ok(())
-                            },
-                            progress(oid, &reader, &mut *stdout),
-                        )
-                        .await?;
-                        let path = channel.keep()?;
-                        self.logs
-                            .write(&logs::Line {
-                                operation: git_lfs::Operation::Download,
-                                oid: Cow::Borrowed(oid),
-                                size,
-                                cache: None,
-                                start,
-                                finish: Utc::now(),
-                            })
-                            .await?;
-                        Ok(path)
-                    } else {
-                        let body = body.collect().await?.to_bytes();
-                        Err(git_lfs::Error {
-                            code: parts.status,
-                            message: format!("{body:?}"),
+                                misc::request(
+                                    &self.client,
+                                    Method::GET,
+                                    download.href.clone(),
+                                    headers,
+                                    Bytes::new(),
+                                )
+                                .await
+                            }
+                        },
+                        size,
+                        self.segments,
+                        &self.retry,
+                        writer,
+                    ),
+                    async {
+                        if let Some(cache) = cache {
+                            let result = cache.put(oid, size, &reader).await;
+                            self.health.lock().await.record(&result);
+                            result?;
                         }
-                        .into())
+                        Ok(())
+                    },
+                    progress(&reader, state),
+                )
+                .await?;
+                Ok(())
+            }
+            git_lfs::batch::response::Inner::Actions { download: None, .. } => {
+                Err(anyhow::format_err!("missing action"))
+            }
+            git_lfs::batch::response::Inner::Error(e) => {
+                // only objects known to be missing, not throttling or server errors
+                if e.code == StatusCode::NOT_FOUND || e.code == StatusCode::GONE {
+                    self.negative.lock().await.insert(oid, e.clone()).await?;
+                }
+                Err(e.into())
+            }
+        }
+    }
+}
+
+// returns the cache source if the cache won
+async fn race<C, O>(
+    hedge: Option<&Hedge>,
+    cache: Option<C>,
+    origin: O,
+    state: &Mutex<Progress<'_>>,
+) -> anyhow::Result<Option<cache::Source>>
+where
+    C: Future<Output = anyhow::Result<cache::Source>>,
+    O: Future<Output = anyhow::Result<()>>,
+{
+    let Some(cache) = cache else {
+        origin.await?;
+        return Ok(None);
+    };
+    let mut cache = pin::pin!(cache);
+    let origin = pin::pin!(origin);
+
+    if let Some(hedge) = hedge {
+        let start = Instant::now();
+        let interval = Duration::from_millis(hedge.timeout);
+        loop {
+            match tokio::time::timeout(interval, &mut cache).await {
+                Ok(Ok(source)) => return Ok(Some(source)),
+                Ok(Err(_)) => {
+                    origin.await?;
+                    return Ok(None);
+                }
+                Err(_) => {
+                    let bytes_so_far = state.lock().await.bytes_so_far;
+                    let throughput = bytes_so_far as f64 / start.elapsed().as_secs_f64();
+                    if bytes_so_far == 0
+                        || hedge
+                            .min_throughput
+                            .is_some_and(|min_throughput| throughput < min_throughput as f64)
+                    {
+                        tracing::info!(bytes_so_far, throughput, "hedging with origin");
+                        break;
                     }
                 }
-                git_lfs::batch::response::Inner::Actions { download: None, .. } => {
-                    Err(anyhow::format_err!("missing action"))
+            }
+        }
+        // the loser is dropped, which cancels it
+        match futures::future::select(cache, origin).await {
+            Either::Left((Ok(source), _)) => {
+                tracing::info!(winner = "cache");
+                Ok(Some(source))
+            }
+            Either::Left((Err(_), origin)) => {
+                origin.await?;
+                tracing::info!(winner = "origin");
+                Ok(None)
+            }
+            Either::Right((Ok(()), _)) => {
+                tracing::info!(winner = "origin");
+                Ok(None)
+            }
+            Either::Right((Err(e), cache)) => {
+                tracing::warn!(error = ?e);
+                let source = cache.await?;
+                tracing::info!(winner = "cache");
+                Ok(Some(source))
+            }
+        }
+    } else if let Ok(source) = cache.await {
+        Ok(Some(source))
+    } else {
+        origin.await?;
+        Ok(None)
+    }
+}
+
+impl Health {
+    fn available(&mut self) -> bool {
+        match self.bypass_until {
+            Some(bypass_until) if Instant::now() < bypass_until => false,
+            Some(_) => {
+                tracing::info!("cache no longer bypassed");
+                self.failures = 0;
+                self.bypass_until = None;
+                true
+            }
+            None => true,
+        }
+    }
+
+    fn record<T>(&mut self, result: &anyhow::Result<T>) {
+        let Some(circuit_breaker) = &self.circuit_breaker else {
+            return;
+        };
+        match result {
+            Err(e) if !cache::not_found(e) => {
+                self.failures += 1;
+                if self.failures >= circuit_breaker.failures && self.bypass_until.is_none() {
+                    let cool_down = Duration::from_millis(circuit_breaker.cool_down);
+                    tracing::warn!(
+                        failures = self.failures,
+                        ?cool_down,
+                        "cache bypassed after consecutive failures",
+                    );
+                    self.bypass_until = Some(Instant::now() + cool_down);
                 }
-                git_lfs::batch::response::Inner::Error(e) => Err(e.into()),
             }
+            _ => self.failures = 0,
         }
     }
 }
 
-async fn progress(
-    oid: &str,
-    reader: &channel::Reader<'_>,
-    stdout: &mut jsonl::Writer<io::Stdout>,
-) -> anyhow::Result<()> {
-    let mut bytes_so_far = 0;
-    let mut bytes_since_last = 0;
+impl Negative {
+    async fn load(args: NegativeCache, git_dir: &Path) -> anyhow::Result<Self> {
+        let path = args.persist.then(|| {
+            git_dir
+                .join(env!("CARGO_PKG_NAME").trim_start_matches("git-"))
+                .join("negative.json")
+        });
+        let entries = if let Some(path) = &path {
+            Self::read(path).await?
+        } else {
+            HashMap::new()
+        };
+        Ok(Self {
+            ttl: chrono::Duration::milliseconds(args.ttl as _),
+            path,
+            entries,
+        })
+    }
 
-    let mut body = pin::pin!(reader.stream()?);
-    while let Some(data) = body.try_next().await? {
-        bytes_so_far += data.len() as u64;
-        bytes_since_last += data.len() as u64;
+    async fn read(path: &Path) -> anyhow::Result<HashMap<String, NegativeEntry>> {
+        let mut entries = HashMap::new();
+        if fs::try_exists(path).await? {
+            entries =
+                serde_json::from_slice::<HashMap<String, NegativeEntry>>(&fs::read(path).await?)?;
+            entries.retain(|_, entry| entry.expires > Utc::now());
+        }
+        Ok(entries)
+    }
+
+    fn get(&mut self, oid: &str) -> Option<git_lfs::Error> {
+        self.entries.retain(|_, entry| entry.expires > Utc::now());
+        self.entries.get(oid).map(|entry| entry.error.clone())
+    }
+
+    async fn insert(&mut self, oid: &str, error: git_lfs::Error) -> anyhow::Result<()> {
+        self.entries.insert(
+            oid.to_string(),
+            NegativeEntry {
+                error,
+                expires: Utc::now() + self.ttl,
+            },
+        );
+        if let Some(path) = &self.path {
+            // other transfer agents may have added entries since
+            for (oid, entry) in Self::read(path).await? {
+                match self.entries.get(&oid) {
+                    Some(current) if current.expires >= entry.expires => {}
+                    _ => {
+                        self.entries.insert(oid, entry);
+                    }
+                }
+            }
+            let dir = path
+                .parent()
+                .ok_or_else(|| anyhow::format_err!("missing parent"))?;
+            fs::create_dir_all(dir).await?;
+            let temp = tempfile::NamedTempFile::new_in(dir)?;
+            fs::write(temp.path(), serde_json::to_vec(&self.entries)?).await?;
+            temp.persist(path)?;
+        }
+        Ok(())
+    }
+}
+
+struct Progress<'a> {
+    oid: &'a str,
+    stdout: &'a mut jsonl::Writer<io::Stdout>,
+    bytes_so_far: u64,
+    bytes_reported: u64,
+}
 
-        if bytes_since_last >= 1 << 16 {
-            stdout
+impl Progress<'_> {
+    async fn update(&mut self, bytes_so_far: u64, flush: bool) -> anyhow::Result<()> {
+        self.bytes_so_far = self.bytes_so_far.max(bytes_so_far);
+        let bytes_since_last = self.bytes_so_far - self.bytes_reported;
+        if bytes_since_last >= 1 << 16 || (flush && bytes_since_last > 0) {
+            self.stdout
                 .write(&git_lfs::custom_transfers::Response::Progress {
-                    oid,
-                    bytes_so_far,
+                    oid: self.oid,
+                    bytes_so_far: self.bytes_so_far,
                     bytes_since_last,
                 })
                 .await?;
-            bytes_since_last = 0;
+            self.bytes_reported = self.bytes_so_far;
         }
+        Ok(())
     }
-    if bytes_since_last > 0 {
-        stdout
-            .write(&git_lfs::custom_transfers::Response::Progress {
-                oid,
-                bytes_so_far,
-                bytes_since_last,
-            })
-            .await?;
+}
+
+// sources racing for the same object share the progress, git-lfs sees the furthest one
+async fn progress(reader: &channel::Reader<'_>, state: &Mutex<Progress<'_>>) -> anyhow::Result<()> {
+    let mut bytes_so_far = 0;
+    let mut body = pin::pin!(reader.stream()?);
+    while let Some(data) = body.try_next().await? {
+        bytes_so_far += data.len() as u64;
+        state.lock().await.update(bytes_so_far, false).await?;
     }
-    Ok(())
+    state.lock().await.update(bytes_so_far, true).await
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,90 @@
+use super::{CircuitBreaker, Hedge, Negative, NegativeCache, Progress, race};
+use crate::{cache, git_lfs, jsonl};
+use http::StatusCode;
+use std::time::Duration;
+use tokio::io;
+use tokio::sync::Mutex;
+
+fn filesystem_source() -> anyhow::Result<cache::Source> {
+    Ok(serde_json::from_value(serde_json::json!({
+        "filesystem": {"path": "/"},
+    }))?)
+}
+
+#[tokio::test]
+async fn test_race_hedged() -> anyhow::Result<()> {
+    let mut stdout = jsonl::Writer::new(io::stdout());
+    let state = Mutex::new(Progress {
+        oid: "",
+        stdout: &mut stdout,
+        bytes_so_far: 0,
+        bytes_reported: 0,
+    });
+    let hedge = Hedge {
+        timeout: 10,
+        min_throughput: None,
+    };
+
+    // a stalled cache loses to the origin
+    let source = tokio::time::timeout(
+        Duration::from_secs(10),
+        race(
+            Some(&hedge),
+            Some(futures::future::pending()),
+            async { Ok(()) },
+            &state,
+        ),
+    )
+    .await??;
+    anyhow::ensure!(source.is_none());
+
+    // a cache answering in time never starts the origin
+    let source = race(
+        Some(&hedge),
+        Some(async { filesystem_source() }),
+        futures::future::pending(),
+        &state,
+    )
+    .await?;
+    anyhow::ensure!(source.is_some());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_negative_merge() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let args = NegativeCache {
+        ttl: 60_000,
+        persist: true,
+    };
+    let error = git_lfs::Error {
+        code: StatusCode::NOT_FOUND,
+        message: "missing".to_string(),
+    };
+
+    // two transfer agents loaded before either inserted
+    let mut a = Negative::load(args.clone(), temp_dir.path()).await?;
+    let mut b = Negative::load(args.clone(), temp_dir.path()).await?;
+    a.insert("a", error.clone()).await?;
+    b.insert("b", error.clone()).await?;
+
+    let mut c = Negative::load(args, temp_dir.path()).await?;
+    anyhow::ensure!(c.get("a").is_some());
+    anyhow::ensure!(c.get("b").is_some());
+
+    Ok(())
+}
+
+#[test]
+fn test_partial_args() -> anyhow::Result<()> {
+    let circuit_breaker: CircuitBreaker = r#"{"failures": 3}"#.parse().map_err(anyhow::Error::msg)?;
+    anyhow::ensure!(circuit_breaker.failures == 3 && circuit_breaker.cool_down == 60_000);
+    let negative_cache: NegativeCache = r#"{"persist": true}"#.parse().map_err(anyhow::Error::msg)?;
+    anyhow::ensure!(negative_cache.persist && negative_cache.ttl == 300_000);
+    let hedge: Hedge = r#"{"min_throughput": 1048576}"#
+        .parse()
+        .map_err(anyhow::Error::msg)?;
+    anyhow::ensure!(hedge.timeout == 3_000 && hedge.min_throughput == Some(1 << 20));
+    Ok(())
+}